@@ -0,0 +1,20 @@
+// Example custom render shader, enable it with `shader = "shaders/glow.wgsl"` in `[display]`.
+// Colors each pixel by its most concentrated species and pulses the brightness over time.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let p = pixel(in.tex_coords);
+
+    var color = vec3<f32>(0.0);
+    var strongest = 0.0;
+    for (var i = 0u; i < frame.species_count; i++) {
+        let w = weight(i, p);
+        if (w > strongest) {
+            strongest = w;
+            color = species[i].color;
+        }
+    }
+
+    let pulse = 0.75 + 0.25 * sin(frame.time * 2.0);
+    return vec4<f32>(post_process(color * sqrt(strongest) * pulse, in.tex_coords), 1.0);
+}
@@ -0,0 +1,206 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::Agent;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentMode {
+    Off,
+    Points,
+    /// Small triangles pointing along each agent's heading.
+    Triangles,
+}
+
+impl AgentMode {
+    pub fn next(self) -> Self {
+        match self {
+            AgentMode::Off => AgentMode::Points,
+            AgentMode::Points => AgentMode::Triangles,
+            AgentMode::Triangles => AgentMode::Off,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentRenderSettings {
+    pub mode: AgentMode,
+    /// Triangle size in pixels.
+    pub size: f32,
+}
+
+impl Default for AgentRenderSettings {
+    fn default() -> Self {
+        Self {
+            mode: AgentMode::Off,
+            size: 3.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct View {
+    resolution: [f32; 2],
+    size: f32,
+    intensity: f32,
+}
+
+/// Draws the agent buffer directly, one instance per agent, scaling species colors by `intensity`.
+pub struct AgentRenderer {
+    point_pipeline: wgpu::RenderPipeline,
+    triangle_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl AgentRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        blend: wgpu::BlendState,
+        species_buffer: &wgpu::Buffer,
+        resolution: [f32; 2],
+        size: f32,
+        intensity: f32,
+    ) -> Self {
+        let view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[View {
+                resolution,
+                size,
+                intensity,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: species_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: view_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(include_species_wgsl!("agents.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |entry_point: &str, topology: wgpu::PrimitiveTopology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point,
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<Agent>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: mem::offset_of!(Agent, position) as wgpu::BufferAddress,
+                                shader_location: 0,
+                                format: wgpu::VertexFormat::Float32x2,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: mem::offset_of!(Agent, angle) as wgpu::BufferAddress,
+                                shader_location: 1,
+                                format: wgpu::VertexFormat::Float32,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: mem::offset_of!(Agent, species) as wgpu::BufferAddress,
+                                shader_location: 2,
+                                format: wgpu::VertexFormat::Uint32,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: mem::offset_of!(Agent, alive) as wgpu::BufferAddress,
+                                shader_location: 3,
+                                format: wgpu::VertexFormat::Uint32,
+                            },
+                        ],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        Self {
+            point_pipeline: pipeline("vs_point", wgpu::PrimitiveTopology::PointList),
+            triangle_pipeline: pipeline("vs_triangle", wgpu::PrimitiveTopology::TriangleList),
+            bind_group,
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        agents_buffer: &'a wgpu::Buffer,
+        agent_count: u32,
+        mode: AgentMode,
+    ) {
+        let (pipeline, vertices) = match mode {
+            AgentMode::Off => return,
+            AgentMode::Points => (&self.point_pipeline, 1),
+            AgentMode::Triangles => (&self.triangle_pipeline, 3),
+        };
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, agents_buffer.slice(..));
+        render_pass.draw(0..vertices, 0..agent_count);
+    }
+}
@@ -0,0 +1,71 @@
+struct View {
+    resolution: vec2<f32>,
+    size: f32,
+    intensity: f32,
+}
+
+@group(0)
+@binding(0)
+var<storage> species: array<Species>;
+
+@group(0)
+@binding(1)
+var<uniform> view: View;
+
+struct AgentInput {
+    @location(0) position: vec2<f32>,
+    @location(1) angle: f32,
+    @location(2) species: u32,
+    @location(3) alive: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+const OUTSIDE: vec4<f32> = vec4<f32>(2.0, 2.0, 0.0, 1.0);
+
+fn to_clip(position: vec2<f32>) -> vec4<f32> {
+    let ndc = position / view.resolution * 2.0 - 1.0;
+    return vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+}
+
+@vertex
+fn vs_point(agent: AgentInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = select(OUTSIDE, to_clip(agent.position), agent.alive != 0u);
+    out.color = species[agent.species].color * view.intensity;
+    return out;
+}
+
+// A triangle pointing along the agent's heading.
+@vertex
+fn vs_triangle(@builtin(vertex_index) index: u32, agent: AgentInput) -> VertexOutput {
+    var corner: vec2<f32>;
+    switch index {
+        case 0u {
+            corner = vec2<f32>(1.5, 0.0);
+        }
+        case 1u {
+            corner = vec2<f32>(-1.0, 0.8);
+        }
+        default {
+            corner = vec2<f32>(-1.0, -0.8);
+        }
+    }
+
+    let c = cos(agent.angle);
+    let s = sin(agent.angle);
+    let rotated = vec2<f32>(corner.x * c - corner.y * s, corner.x * s + corner.y * c);
+
+    var out: VertexOutput;
+    out.position = select(OUTSIDE, to_clip(agent.position + rotated * view.size), agent.alive != 0u);
+    out.color = species[agent.species].color * view.intensity;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,68 @@
+use anyhow::{bail, Result};
+use wgpu::util::DeviceExt;
+
+/// Decodes a PNG into RGBA8, stretched to `width`x`height` with nearest sampling.
+pub fn read_png(bytes: &[u8], width: u32, height: u32) -> Result<Vec<[u8; 4]>> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => bail!("unsupported indexed PNG"),
+    };
+
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let source_x = (x as u64 * info.width as u64 / width as u64) as usize;
+            let source_y = (y as u64 * info.height as u64 / height as u64) as usize;
+            let texel = &buffer[source_y * info.line_size + source_x * channels..];
+
+            pixels.push(match channels {
+                1 => [texel[0], texel[0], texel[0], 255],
+                2 => [texel[0], texel[0], texel[0], texel[1]],
+                3 => [texel[0], texel[1], texel[2], 255],
+                _ => [texel[0], texel[1], texel[2], texel[3]],
+            });
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Uploads two values per pixel into a texture the compute shader reads with `textureLoad`.
+pub fn texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    values: &[[f32; 2]],
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(values),
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
@@ -1,23 +1,25 @@
-struct Species {
-    color: vec3<f32>,
-    amount: u32,
-    move_speed: f32,
-    turn_speed: f32,
-    sensor_angle: f32,
-    sensor_offset: f32,
-    sensor_size: i32,
-    decay_rate: f32,
-    diffuse_rate: f32,
-    like_index: u32,
-    like_length: u32,
-    hate_index: u32,
-    hate_length: u32,
+struct Display {
+    background: vec3<f32>,
+    mode: u32,
+    source: u32,
+    exposure: f32,
+    gamma: f32,
+    histograms: u32,
+    hdr: u32,
 }
 
 struct Agent {
     position: vec2<f32>,
     angle: f32,
     species: u32,
+    move_speed_scale: f32,
+    turn_speed_scale: f32,
+    sensor_angle_scale: f32,
+    sensor_offset_scale: f32,
+    energy: f32,
+    alive: u32,
+    fitness: f32,
+    claim: u32,
 }
 
 @group(0)
@@ -60,6 +62,89 @@ var<storage, read_write> weights: array<f32>;
 @binding(9)
 var<storage> time_delta: f32;
 
+@group(0)
+@binding(10)
+var<uniform> display: Display;
+
+@group(0)
+@binding(11)
+var trails: texture_2d<f32>;
+
+// Counters and lists for the lifecycle passes, reset every frame except for the lists: [births,
+// free, populations per species, histograms per species, parents of births per slot, free slots
+// per slot]. Each species has a histogram of `HISTOGRAM_BINS` bins for each of the agents' four
+// parameter scales over [0, 2) and their fitness over [0, 2 * reproduce_energy).
+@group(0)
+@binding(12)
+var<storage, read_write> lifecycle: array<atomic<u32>>;
+
+// Velocity in pixels per second, 1x1 and zero when the flow is off.
+@group(0)
+@binding(13)
+var flow_field: texture_2d<f32>;
+
+// The weights after advection, copied back by `main_advect_store`.
+@group(0)
+@binding(14)
+var<storage, read_write> advected: array<f32>;
+
+struct Flow {
+    agent_drift: f32,
+}
+
+@group(0)
+@binding(15)
+var<uniform> flow: Flow;
+
+// Multipliers of the decay and diffusion rates, 1x1 and one when no map is set.
+@group(0)
+@binding(16)
+var environment: texture_2d<f32>;
+
+// How `main_1` orders turning and moving, see `Motion`.
+@group(0)
+@binding(17)
+var<uniform> motion: u32;
+
+const MOTION_ORDERED: u32 = 0u;
+const MOTION_LEGACY: u32 = 1u;
+
+// Number of exclusive agents claiming each pixel, at most one except for races that back off
+// again. A single element when no species is exclusive.
+@group(0)
+@binding(18)
+var<storage, read_write> occupancy: array<atomic<u32>>;
+
+const NO_SPECIES: u32 = 0xffffffffu;
+
+const BIRTHS: u32 = 0u;
+const FREE: u32 = 1u;
+const POPULATIONS: u32 = 2u;
+
+const HISTOGRAM_PARAMETERS: u32 = 5u;
+const HISTOGRAM_BINS: u32 = 32u;
+
+fn histograms_offset() -> u32 {
+    return POPULATIONS + arrayLength(&species);
+}
+
+fn births_offset() -> u32 {
+    return histograms_offset() + arrayLength(&species) * HISTOGRAM_PARAMETERS * HISTOGRAM_BINS;
+}
+
+fn free_offset() -> u32 {
+    return births_offset() + arrayLength(&agents);
+}
+
+const MODE_BLEND: u32 = 0u;
+const MODE_ADDITIVE: u32 = 1u;
+const MODE_MAX: u32 = 2u;
+const MODE_VIRIDIS: u32 = 3u;
+const MODE_MAGMA: u32 = 4u;
+const MODE_INFERNO: u32 = 5u;
+const MODE_GRAYSCALE: u32 = 6u;
+const MODE_TRAILS: u32 = 7u;
+
 fn hash(state: u32) -> u32 {
     var hash = state;
     hash ^= 2747636419u;
@@ -71,12 +156,66 @@ fn hash(state: u32) -> u32 {
     return hash;
 }
 
+// Average concentration of one species in the square of `size` around a position.
+fn concentration(position: vec2<f32>, species_index: u32, size: i32) -> f32 {
+    let species_length = arrayLength(&species);
+
+    var sum = 0.0;
+    var count = 0.0;
+
+    for (var offset_x = -size; offset_x <= size; offset_x++) {
+        for (var offset_y = -size; offset_y <= size; offset_y++) {
+            let pos = vec2<i32>(position) + vec2<i32>(offset_x, offset_y);
+
+            if (pos.x >= 0 && pos.x < i32(width) && pos.y >= 0 && pos.y < i32(height)) {
+                let weight_index = (u32(pos.x) * species_length + u32(pos.y) * width * species_length) + species_index;
+                sum += weights[weight_index];
+                count += 1.0;
+            }
+        }
+    }
+
+    return sum / max(count, 1.0);
+}
+
+const STEERING_CLASSIC: u32 = 0u;
+const STEERING_GRADIENT: u32 = 1u;
+const STEERING_SOFTMAX: u32 = 2u;
+const STEERING_AVOID_CROWDING: u32 = 3u;
+
+const SHAPE_CIRCLE: u32 = 1u;
+const WEIGHTING_LINEAR: u32 = 1u;
+
+// Liked minus hated pheromone at a pixel, zero outside of the field.
+fn attraction(spec: Species, pos: vec2<i32>) -> f32 {
+    if (pos.x < 0 || pos.x >= i32(width) || pos.y < 0 || pos.y >= i32(height)) {
+        return 0.0;
+    }
+
+    let species_length = arrayLength(&species);
+    let pixel_index = (u32(pos.x) + u32(pos.y) * width) * species_length;
+    var sum = 0.0;
+
+    let like_end = spec.like_index + spec.like_length;
+    for (var like_index = spec.like_index; like_index < like_end; like_index++) {
+        sum += weights[pixel_index + relations[like_index]];
+    }
+
+    let hate_end = spec.hate_index + spec.hate_length;
+    for (var hate_index = spec.hate_index; hate_index < hate_end; hate_index++) {
+        sum -= weights[pixel_index + relations[hate_index]];
+    }
+
+    return sum;
+}
+
+// Reading of the sensor at `dir` times the sensor angle, over the species' footprint.
 fn sense(i: u32, dir: f32) -> f32 {
     let agent = agents[i];
     let spec = species[agent.species];
 
-    let sensor_angle = spec.sensor_angle * dir;
-    let sensor_offset = spec.sensor_offset;
+    let sensor_angle = spec.sensor_angle * agent.sensor_angle_scale * dir;
+    let sensor_offset = spec.sensor_offset * agent.sensor_offset_scale;
     let sensor_size = spec.sensor_size;
 
     let angle = agent.angle + sensor_angle;
@@ -87,25 +226,30 @@ fn sense(i: u32, dir: f32) -> f32 {
 
     for (var offset_x = -sensor_size; offset_x <= sensor_size; offset_x++) {
         for (var offset_y = -sensor_size; offset_y <= sensor_size; offset_y++) {
-            let pos = vec2<u32>(position + vec2<f32>(f32(offset_x), f32(offset_y)));
-
-            if (pos.x >= 0u && pos.x < width && pos.y >= 0u && pos.y < height) {
-
-                let like_end = spec.like_index + spec.like_length;
-                for (var like_index = spec.like_index; like_index < like_end; like_index++) {
-                    let liked_species = relations[like_index];
-                    let species_length = arrayLength(&species);
-                    let weight_index = (pos.x * species_length + pos.y * width * species_length) + liked_species;
-                    sum += weights[weight_index];
-                }
-
-                let hate_end = spec.hate_index + spec.hate_length;
-                for (var hate_index = spec.hate_index; hate_index < hate_end; hate_index++) {
-                    let hated_species = relations[hate_index];
-                    let species_length = arrayLength(&species);
-                    let weight_index = (pos.x * species_length + pos.y * width * species_length) + hated_species;
-                    sum -= weights[weight_index];
-                }
+            let offset = vec2<f32>(f32(offset_x), f32(offset_y));
+            let distance = length(offset);
+
+            if (spec.sensor_shape == SHAPE_CIRCLE && distance > f32(sensor_size)) {
+                continue;
+            }
+
+            var factor = 1.0;
+            if (spec.sensor_weighting == WEIGHTING_LINEAR) {
+                factor = max(0.0, 1.0 - distance / f32(sensor_size + 1));
+            }
+
+            let sample = position + offset;
+
+            if (spec.sensor_bilinear != 0u) {
+                let base = floor(sample - 0.5);
+                let t = sample - 0.5 - base;
+                let pos = vec2<i32>(base);
+
+                let top = mix(attraction(spec, pos), attraction(spec, pos + vec2<i32>(1, 0)), t.x);
+                let bottom = mix(attraction(spec, pos + vec2<i32>(0, 1)), attraction(spec, pos + vec2<i32>(1, 1)), t.x);
+                sum += mix(top, bottom, t.y) * factor;
+            } else {
+                sum += attraction(spec, vec2<i32>(vec2<u32>(sample))) * factor;
             }
         }
     }
@@ -113,12 +257,15 @@ fn sense(i: u32, dir: f32) -> f32 {
     return sum;
 }
 
+// Updates one agent: sense at its position and heading, turn, move along the new heading and deposit
+// at the new position. Legacy motion moves along the heading from before the turn instead, so turns
+// only show up in the next step.
 @compute
 @workgroup_size(1, 1, 1)
 fn main_1(@builtin(global_invocation_id) id: vec3<u32>) {
     let i = id.x + id.y * x + id.z * x * y;
 
-    if (i >= arrayLength(&agents)) {
+    if (i >= arrayLength(&agents) || agents[i].alive == 0u) {
         return;
     }
 
@@ -127,27 +274,152 @@ fn main_1(@builtin(global_invocation_id) id: vec3<u32>) {
     let position = agent.position;
     let angle = agent.angle;
 
-    let move_speed = spec.move_speed;
-    let turn_speed = spec.turn_speed * 2.0 * 3.1415;
+    let move_speed = spec.move_speed * agent.move_speed_scale;
+    let turn_speed = spec.turn_speed * agent.turn_speed_scale * 2.0 * 3.1415;
 
     let weight_forward = sense(i, 0.0);
-    let weight_left = sense(i, 1.0);
-    let weight_right = sense(i, -1.0);
+    var weight_left = -1e30;
+    var weight_right = -1e30;
+    var dir_left = 0.0;
+    var dir_right = 0.0;
+    var worst_side = 1e30;
+    var gradient = 0.0;
+    var gradient_scale = 0.0001;
+
+    // Softmax choice among all sensors, made while sensing by keeping each candidate with its
+    // share of the running total.
+    let steering_seed = hash(u32(position.y) * width + u32(position.x) + hash(i) + 1u);
+    var softmax_total = 1.0;
+    var softmax_dir = 0.0;
+
+    let pairs = (spec.sensors - 1u) / 2u;
+    for (var pair = 1u; pair <= pairs; pair++) {
+        let dir = f32(pair) / f32(pairs);
+        let left = sense(i, dir);
+        let right = sense(i, -dir);
+
+        if (left > weight_left) {
+            weight_left = left;
+            dir_left = dir;
+        }
+        if (right > weight_right) {
+            weight_right = right;
+            dir_right = dir;
+        }
+        worst_side = min(worst_side, min(left, right));
+
+        gradient += dir * (left - right);
+        gradient_scale += dir * (abs(left) + abs(right));
+
+        for (var side = 0u; side < 2u; side++) {
+            let reading = select(left, right, side == 1u);
+            let share = exp(clamp((reading - weight_forward) / spec.steering_temperature, -80.0, 80.0));
+            softmax_total += share;
+
+            let chance = f32(hash(steering_seed + pair * 2u + side)) / 4294967295.0;
+            if (chance * softmax_total < share) {
+                softmax_dir = select(dir, -dir, side == 1u);
+            }
+        }
+    }
+
+    let gain = spec.gain_rate * max(weight_forward, 0.0) * time_delta;
+    let energy = agent.energy + gain - spec.drain_rate * time_delta;
+    let fitness = agent.fitness + gain;
+
+    if (energy <= 0.0) {
+        agents[i].alive = 0u;
+        release(i);
+        return;
+    }
+
+    if (spec.reproduce_energy > 0.0 && energy >= spec.reproduce_energy) {
+        let birth = atomicAdd(&lifecycle[BIRTHS], 1u);
+
+        if (birth < arrayLength(&agents)) {
+            atomicStore(&lifecycle[births_offset() + birth], i);
+        }
+    }
+
+    agents[i].energy = energy;
+    agents[i].fitness = fitness;
+
+    var species_index = agent.species;
+
+    if (spec.convert_to != NO_SPECIES) {
+        let chance = f32(hash(hash(i) ^ (u32(position.x) * height + u32(position.y)))) / 4294967295.0;
+        let surrounding = concentration(position, spec.convert_to, spec.sensor_size);
+
+        if (surrounding >= spec.convert_threshold && chance < spec.convert_probability * time_delta) {
+            species_index = spec.convert_to;
+            agents[i].species = species_index;
+
+            if (species[species_index].exclusive == 0u) {
+                release(i);
+            }
+        }
+    }
+
+    atomicAdd(&lifecycle[POPULATIONS + species_index], 1u);
+
+    if (display.histograms != 0u) {
+        var values = array<f32, HISTOGRAM_PARAMETERS>(
+            agent.move_speed_scale * 0.5,
+            agent.turn_speed_scale * 0.5,
+            agent.sensor_angle_scale * 0.5,
+            agent.sensor_offset_scale * 0.5,
+            fitness / max(2.0 * spec.reproduce_energy, 0.0001),
+        );
+
+        for (var parameter = 0u; parameter < HISTOGRAM_PARAMETERS; parameter++) {
+            let bin = u32(clamp(values[parameter], 0.0, 0.9999) * f32(HISTOGRAM_BINS));
+            let histogram = (species_index * HISTOGRAM_PARAMETERS + parameter) * HISTOGRAM_BINS;
+            atomicAdd(&lifecycle[histograms_offset() + histogram + bin], 1u);
+        }
+    }
 
     let random = f32(hash(u32(position.y) * width + u32(position.x) + hash(i))) / 4294967295.0;
 
-    if (weight_forward > weight_left && weight_forward > weight_right) {
-        agents[i].angle += 0.0;
-    } else if (weight_forward < weight_left && weight_forward < weight_right) {
-        agents[i].angle += (random - 0.5) * 2.0 * turn_speed * time_delta;
-    } else if (weight_right > weight_left) {
-        agents[i].angle -= random * turn_speed * time_delta;
-    } else if (weight_left > weight_right) {
-        agents[i].angle += random * turn_speed * time_delta;
+    var turn = 0.0;
+
+    switch spec.steering {
+        case STEERING_GRADIENT: {
+            turn = gradient / gradient_scale * turn_speed * time_delta;
+        }
+        case STEERING_SOFTMAX: {
+            turn = softmax_dir * random * turn_speed * time_delta;
+        }
+        default: {
+            let crowded = spec.steering == STEERING_AVOID_CROWDING && weight_forward > spec.crowding_threshold;
+
+            // Crowded agents turn toward the weaker side. Otherwise go straight if forward is best,
+            // turn randomly if it is worst, or turn toward the best side, faster the further out its
+            // best sensor is.
+            if (crowded) {
+                turn = select(-1.0, 1.0, weight_right > weight_left) * random * turn_speed * time_delta;
+            } else if (weight_forward > weight_left && weight_forward > weight_right) {
+                turn = 0.0;
+            } else if (weight_forward < worst_side) {
+                turn = (random - 0.5) * 2.0 * turn_speed * time_delta;
+            } else if (weight_right > weight_left) {
+                turn = -random * turn_speed * time_delta * dir_right;
+            } else if (weight_left > weight_right) {
+                turn = random * turn_speed * time_delta * dir_left;
+            }
+        }
     }
 
-    let direction = vec2<f32>(cos(angle), sin(angle));
-    var new_position = position + direction * time_delta * move_speed;
+    let new_angle = angle + turn;
+    agents[i].angle = new_angle;
+
+    var heading = new_angle;
+    if (motion == MOTION_LEGACY) {
+        heading = angle;
+    }
+
+    let direction = vec2<f32>(cos(heading), sin(heading));
+    let drift = flow_at(position) * flow.agent_drift;
+    var new_position = position + (direction * move_speed + drift) * time_delta;
 
     if (new_position.x < 0.0) {
         new_position.x = f32(width) - 1.0;
@@ -161,45 +433,289 @@ fn main_1(@builtin(global_invocation_id) id: vec3<u32>) {
         new_position.y = 0.0;
     }
 
+    // Exclusive agents only move into pixels no other exclusive agent claims, otherwise they stay,
+    // skip depositing and pick a random heading. Read after a possible conversion, which may have
+    // released the claim.
+    if (species[species_index].exclusive != 0u) {
+        let new_cell = u32(new_position.x) + u32(new_position.y) * width;
+
+        if (agents[i].claim != new_cell + 1u) {
+            if (atomicAdd(&occupancy[new_cell], 1u) == 0u) {
+                release(i);
+                agents[i].claim = new_cell + 1u;
+            } else {
+                atomicSub(&occupancy[new_cell], 1u);
+                agents[i].angle = f32(hash(steering_seed + 2u)) / 4294967295.0 * 2.0 * 3.1415;
+                return;
+            }
+        }
+    }
+
     let species_length = arrayLength(&species);
-    let weight_index = (u32(new_position.x) * species_length + u32(new_position.y) * width * species_length) + agent.species;
+    let weight_index = (u32(new_position.x) * species_length + u32(new_position.y) * width * species_length) + species_index;
     weights[weight_index] = 1.0;
 
     agents[i].position = new_position;
 }
 
+// Frees the pixel an agent claims, if any.
+fn release(i: u32) {
+    let claim = agents[i].claim;
+
+    if (claim != 0u) {
+        atomicSub(&occupancy[claim - 1u], 1u);
+        agents[i].claim = 0u;
+    }
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return pow(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+// Polynomial fits of the matplotlib colormaps, evaluated in sRGB.
+fn colormap(mode: u32, t: f32) -> vec3<f32> {
+    var c0: vec3<f32>;
+    var c1: vec3<f32>;
+    var c2: vec3<f32>;
+    var c3: vec3<f32>;
+    var c4: vec3<f32>;
+    var c5: vec3<f32>;
+    var c6: vec3<f32>;
+
+    switch mode {
+        case MODE_VIRIDIS {
+            c0 = vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+            c1 = vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+            c2 = vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+            c3 = vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+            c4 = vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105);
+            c5 = vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234);
+            c6 = vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+        }
+        case MODE_MAGMA {
+            c0 = vec3<f32>(-0.002136485053939582, -0.000749655052795221, -0.005386127855323933);
+            c1 = vec3<f32>(0.2516605407371642, 0.6775232436837668, 2.494026599312351);
+            c2 = vec3<f32>(8.353717279216625, -3.577719514958484, 0.3144679030132573);
+            c3 = vec3<f32>(-27.66873308576866, 14.26473078096533, -13.64921318813922);
+            c4 = vec3<f32>(52.17613981234068, -27.94360607168351, 12.94416944238394);
+            c5 = vec3<f32>(-50.76852536473588, 29.04658282127291, 4.23415299384598);
+            c6 = vec3<f32>(18.65570506591883, -11.48977351997711, -5.601961508734096);
+        }
+        case MODE_INFERNO {
+            c0 = vec3<f32>(0.0002189403691192265, 0.001651004631001012, -0.01948089843709184);
+            c1 = vec3<f32>(0.1065134194856116, 0.5639564367884091, 3.932712388889277);
+            c2 = vec3<f32>(11.60249308247187, -3.972853965665698, -15.9423941062914);
+            c3 = vec3<f32>(-41.70399613139459, 17.43639888205313, 44.35414519872813);
+            c4 = vec3<f32>(77.162935699427, -33.40235894210092, -81.80730925738993);
+            c5 = vec3<f32>(-71.31942824499214, 32.62606426397723, 73.20951985803202);
+            c6 = vec3<f32>(25.13112622477341, -12.24266895238567, -23.07032500287172);
+        }
+        default {
+            return vec3<f32>(t, t, t);
+        }
+    }
+
+    return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+}
+
+// Maps a concentration to a coverage in [0, 1], zero concentration is fully transparent.
+fn intensity(weight: f32) -> f32 {
+    return pow(clamp(weight * display.exposure, 0.0, 1.0), display.gamma);
+}
+
+// Like `intensity`, but left above one for the tonemap to compress when there is one.
+fn brightness(weight: f32) -> f32 {
+    if (display.hdr == 0u) {
+        return intensity(weight);
+    }
+    return pow(max(weight * display.exposure, 0.0), display.gamma);
+}
+
+// Clamps a color to the displayable range unless a tonemap follows.
+fn displayable(color: vec3<f32>) -> vec3<f32> {
+    if (display.hdr == 0u) {
+        return min(color, vec3<f32>(1.0));
+    }
+    return color;
+}
+
+// Collects the slots of dead agents into the free list.
+@compute
+@workgroup_size(1, 1, 1)
+fn main_free(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x + id.y * x + id.z * x * y;
+
+    if (i >= arrayLength(&agents) || agents[i].alive != 0u) {
+        return;
+    }
+
+    let slot = atomicAdd(&lifecycle[FREE], 1u);
+    atomicStore(&lifecycle[free_offset() + slot], i);
+}
+
+// Pairs each birth with a free slot, the child gets half of its parent's energy.
+@compute
+@workgroup_size(1, 1, 1)
+fn main_birth(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x + id.y * x + id.z * x * y;
+
+    let births = min(atomicLoad(&lifecycle[BIRTHS]), arrayLength(&agents));
+    if (i >= min(births, atomicLoad(&lifecycle[FREE]))) {
+        return;
+    }
+
+    let parent = atomicLoad(&lifecycle[births_offset() + i]);
+    let slot = atomicLoad(&lifecycle[free_offset() + i]);
+
+    var child = agents[parent];
+    let energy = child.energy * 0.5;
+    agents[parent].energy = energy;
+
+    let seed = hash(slot + hash(parent)) ^ bitcast<u32>(child.position.x) ^ bitcast<u32>(child.position.y);
+    let random = f32(seed) / 4294967295.0;
+    child.energy = energy;
+    child.fitness = 0.0;
+    child.claim = 0u;
+    child.angle = random * 2.0 * 3.1415;
+
+    let mutation = species[child.species].mutation;
+    child.move_speed_scale = mutate(child.move_speed_scale, mutation, seed + 1u);
+    child.turn_speed_scale = mutate(child.turn_speed_scale, mutation, seed + 2u);
+    child.sensor_angle_scale = mutate(child.sensor_angle_scale, mutation, seed + 3u);
+    child.sensor_offset_scale = mutate(child.sensor_offset_scale, mutation, seed + 4u);
+
+    agents[slot] = child;
+}
+
+// Scales a heritable parameter by a random factor in `1 ± amount`.
+fn mutate(value: f32, amount: f32, seed: u32) -> f32 {
+    let random = f32(hash(seed)) / 4294967295.0;
+    return max(0.0, value * (1.0 + (random - 0.5) * 2.0 * amount));
+}
+
+// Every mode produces a color premultiplied by its coverage, which is then composited over the
+// background, so pixels without pheromone are exactly the background color.
 @compute
 @workgroup_size(1)
 fn main_2(@builtin(global_invocation_id) id: vec3<u32>) {
     let length = arrayLength(&species);
 
     var sum = vec3<f32>(0.0, 0.0, 0.0);
-    var amount = 0;
+    var max_weight = 0.0;
+    var max_color = vec3<f32>(0.0, 0.0, 0.0);
+    var total = 0.0;
+    var source_total = 0.0;
     for (var i = 0u; i < length; i++) {
         let species_length = arrayLength(&species);
         let weight_index = (id.x * species_length + id.y * width * species_length) + i;
         let weight = weights[weight_index];
 
-        if (weight != 0.0) {
-            sum += species[i].color * weights[weight_index];
-            amount++;
+        sum += species[i].color * weight;
+        total += weight;
+
+        if (weight > max_weight) {
+            max_weight = weight;
+            max_color = species[i].color;
+        }
+
+        if (display.source == length || display.source == i) {
+            source_total += weight;
+        }
+    }
+
+    var color: vec3<f32>;
+    var coverage: f32;
+
+    switch display.mode {
+        case MODE_BLEND {
+            coverage = intensity(total);
+            if (total > 0.0) {
+                color = sum / total * brightness(total);
+            }
+        }
+        case MODE_ADDITIVE {
+            coverage = intensity(total);
+            color = displayable(sum * display.exposure);
+        }
+        case MODE_MAX {
+            coverage = intensity(max_weight);
+            color = max_color * brightness(max_weight);
+        }
+        case MODE_TRAILS {
+            color = displayable(textureLoad(trails, id.xy, 0).rgb * display.exposure);
+            coverage = pow(min(max(color.r, max(color.g, color.b)), 1.0), display.gamma);
+        }
+        default {
+            coverage = 1.0;
+            color = srgb_to_linear(colormap(display.mode, intensity(source_total)));
+        }
+    }
+
+    let composited = color + display.background * (1.0 - coverage);
+    textureStore(texture, vec2<i32>(id.xy), vec4<f32>(composited, 1.0));
+}
+
+// Texel at a position, clamped so the 1x1 placeholders of disabled maps apply everywhere.
+fn load_clamped(map: texture_2d<f32>, position: vec2<f32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(map));
+    let pixel = clamp(vec2<i32>(position), vec2<i32>(0), size - 1);
+    return textureLoad(map, pixel, 0);
+}
+
+fn flow_at(position: vec2<f32>) -> vec2<f32> {
+    return load_clamped(flow_field, position).xy;
+}
+
+// Semi-Lagrangian advection: every pixel takes the bilinearly interpolated weights from where the
+// flow carries them from, wrapping around the edges like the agents do.
+@compute
+@workgroup_size(1)
+fn main_advect(@builtin(global_invocation_id) id: vec3<u32>) {
+    let length = arrayLength(&species);
+    let size = vec2<i32>(i32(width), i32(height));
+
+    let source = vec2<f32>(id.xy) - flow_at(vec2<f32>(id.xy)) * time_delta;
+    let base = floor(source);
+    let t = source - base;
+
+    let index = (id.x + id.y * width) * length;
+    for (var i = 0u; i < length; i++) {
+        advected[index + i] = 0.0;
+    }
+
+    for (var corner = 0u; corner < 4u; corner++) {
+        let offset = vec2<u32>(corner & 1u, corner >> 1u);
+        let pixel = ((vec2<i32>(base) + vec2<i32>(offset)) % size + size) % size;
+        let factor = mix(1.0 - t, t, vec2<f32>(offset));
+        let sample_index = u32(pixel.x + pixel.y * size.x) * length;
+
+        for (var i = 0u; i < length; i++) {
+            advected[index + i] += weights[sample_index + i] * factor.x * factor.y;
         }
     }
+}
 
-    let amount_f32 = f32(amount);
-    sum /= vec3<f32>(amount_f32, amount_f32, amount_f32);
-    textureStore(texture, vec2<i32>(id.xy), vec4<f32>(sum, 1.0));
+@compute
+@workgroup_size(1)
+fn main_advect_store(@builtin(global_invocation_id) id: vec3<u32>) {
+    let length = arrayLength(&species);
+    let index = (id.x + id.y * width) * length;
+
+    for (var i = 0u; i < length; i++) {
+        weights[index + i] = advected[index + i];
+    }
 }
 
 @compute
 @workgroup_size(1)
 fn main_3(@builtin(global_invocation_id) id: vec3<u32>) {
     let length = arrayLength(&species);
+    let rates = load_clamped(environment, vec2<f32>(id.xy)).xy;
 
     for (var i = 0u; i < length; i++) {
         let spec = species[i];
-        let decay_rate = spec.decay_rate * time_delta;
-        let diffuse_rate = spec.diffuse_rate * time_delta;
+        let decay_rate = spec.decay_rate * rates.x * time_delta;
+        let diffuse_rate = spec.diffuse_rate * rates.y * time_delta;
 
         var sum = 0.0;
         for (var offset_x = -1; offset_x <= 1; offset_x++) {
@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{screenshot, timeline::Field, Species};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// Listen for commands on localhost.
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7878,
+        }
+    }
+}
+
+/// A command posted as JSON, tagged by its `command` field.
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Get {
+        species: String,
+        field: Field,
+    },
+    Set {
+        species: String,
+        field: Field,
+        value: f32,
+    },
+    Pause,
+    Resume,
+    /// Respawns the agents and pheromone the run started with and restores the species.
+    Reset,
+    Screenshot,
+    Snapshot,
+}
+
+type Reply = Sender<Result<Value, String>>;
+
+/// HTTP server on localhost passing the commands it receives on to the simulation.
+pub struct Control {
+    receiver: Receiver<(Command, Reply)>,
+    port: u16,
+}
+
+impl Control {
+    /// Listens on `port`, or any free port if it is zero.
+    pub fn start(port: u16) -> Result<Self> {
+        let server = Server::http(("127.0.0.1", port))
+            .map_err(|err| anyhow!("failed to listen on port {port}: {err}"))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map_or(port, |address| address.port());
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let (status, body) = respond(&mut request, &sender);
+                let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+                let response = Response::from_string(body.to_string())
+                    .with_status_code(status)
+                    .with_header(header);
+
+                if let Err(err) = request.respond(response) {
+                    eprintln!("failed to answer control request: {err}");
+                }
+            }
+        });
+
+        Ok(Self { receiver, port })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Runs `handle` on every command received since the last poll and sends back its result.
+    pub fn poll(&self, mut handle: impl FnMut(Command) -> Result<Value>) {
+        while let Ok((command, reply)) = self.receiver.try_recv() {
+            let _ = reply.send(handle(command).map_err(|err| err.to_string()));
+        }
+    }
+}
+
+/// The simulation state commands act on, borrowed for one poll. Changes the GPU has to see are
+/// left to the caller, flagged by `species_changed` and `reset`.
+pub struct State<'a> {
+    pub names: &'a HashMap<String, usize>,
+    pub species: &'a mut [Species],
+    pub initial_species: &'a [Species],
+    pub paused: &'a mut bool,
+    /// Directory screenshots and snapshots are saved to.
+    pub dir: &'a Path,
+    pub save_screenshot: &'a dyn Fn(&Path) -> Result<()>,
+    pub save_snapshot: &'a dyn Fn(&Path) -> Result<()>,
+    pub species_changed: bool,
+    /// Whether the agents, pheromone and time have to be restored, the species already are.
+    pub reset: bool,
+}
+
+impl State<'_> {
+    pub fn handle(&mut self, command: Command) -> Result<Value> {
+        let names = self.names;
+        let index = |name: &str| {
+            names
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("unknown species {name}"))
+        };
+
+        match command {
+            Command::Get { species, field } => {
+                Ok(json!(*field.get(&mut self.species[index(&species)?])))
+            }
+            Command::Set {
+                species,
+                field,
+                value,
+            } => {
+                *field.get(&mut self.species[index(&species)?]) = value;
+                self.species_changed = true;
+                Ok(Value::Null)
+            }
+            Command::Pause => {
+                *self.paused = true;
+                Ok(Value::Null)
+            }
+            Command::Resume => {
+                *self.paused = false;
+                Ok(Value::Null)
+            }
+            Command::Reset => {
+                self.species.copy_from_slice(self.initial_species);
+                self.species_changed = true;
+                self.reset = true;
+                Ok(Value::Null)
+            }
+            Command::Screenshot => {
+                let path = screenshot::timestamped_path(self.dir, "screenshot", "png");
+                (self.save_screenshot)(&path)?;
+                Ok(json!(path.display().to_string()))
+            }
+            Command::Snapshot => {
+                let path = screenshot::timestamped_path(self.dir, "snapshot", "snapshot");
+                (self.save_snapshot)(&path)?;
+                Ok(json!(path.display().to_string()))
+            }
+        }
+    }
+}
+
+fn respond(request: &mut Request, sender: &Sender<(Command, Reply)>) -> (u16, Value) {
+    let error = |err: &dyn std::fmt::Display| json!({ "ok": false, "error": err.to_string() });
+
+    if *request.method() != Method::Post {
+        return (405, error(&"commands must be POSTed"));
+    }
+
+    let mut body = String::new();
+    if let Err(err) = request.as_reader().read_to_string(&mut body) {
+        return (400, error(&err));
+    }
+
+    let command = match serde_json::from_str(&body) {
+        Ok(command) => command,
+        Err(err) => return (400, error(&err)),
+    };
+
+    let (reply, result) = mpsc::channel();
+    if sender.send((command, reply)).is_err() {
+        return (503, error(&"the simulation has stopped"));
+    }
+
+    match result.recv() {
+        Ok(Ok(value)) => (200, json!({ "ok": true, "result": value })),
+        Ok(Err(err)) => (422, error(&err)),
+        Err(_) => (503, error(&"the simulation has stopped")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use super::*;
+
+    fn post(port: u16, body: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn client_drives_commands() {
+        let control = Control::start(0).unwrap();
+        let port = control.port();
+
+        let client = thread::spawn(move || {
+            [
+                r#"{ "command": "get", "species": "white", "field": "sensor_angle" }"#,
+                r#"{ "command": "set", "species": "red", "field": "move_speed", "value": 2 }"#,
+                r#"{ "command": "pause" }"#,
+                r#"{ "command": "fly" }"#,
+            ]
+            .map(|body| post(port, body))
+        });
+
+        let mut paused = false;
+        while !client.is_finished() {
+            control.poll(|command| match command {
+                Command::Get {
+                    species,
+                    field: Field::SensorAngle,
+                } if species == "white" => Ok(json!(0.5)),
+                Command::Set { species, .. } => Err(anyhow!("unknown species {species}")),
+                Command::Pause => {
+                    paused = true;
+                    Ok(Value::Null)
+                }
+                _ => unreachable!(),
+            });
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let [get, set, pause, unknown] = client.join().unwrap();
+        assert!(get.starts_with("HTTP/1.1 200"));
+        assert!(get.ends_with(r#"{"ok":true,"result":0.5}"#));
+        assert!(set.starts_with("HTTP/1.1 422"));
+        assert!(set.ends_with(r#"{"error":"unknown species red","ok":false}"#));
+        assert!(pause.ends_with(r#"{"ok":true,"result":null}"#));
+        assert!(paused);
+        assert!(unknown.starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn state_applies_commands() {
+        let names = HashMap::from([("white".to_string(), 0), ("red".to_string(), 1)]);
+        let mut initial_species: [Species; 2] = bytemuck::Zeroable::zeroed();
+        initial_species[1].move_speed = 3.0;
+        let mut species = initial_species;
+        let mut paused = false;
+        let saved = std::cell::RefCell::new(Vec::new());
+        let save = |path: &Path| {
+            saved.borrow_mut().push(path.to_path_buf());
+            Ok(())
+        };
+
+        let mut state = State {
+            names: &names,
+            species: &mut species,
+            initial_species: &initial_species,
+            paused: &mut paused,
+            dir: Path::new("out"),
+            save_screenshot: &save,
+            save_snapshot: &|_| Err(anyhow!("disk full")),
+            species_changed: false,
+            reset: false,
+        };
+        let mut run = |body: &str| state.handle(serde_json::from_str(body).unwrap());
+
+        let set = r#"{ "command": "set", "species": "red", "field": "move_speed", "value": 2 }"#;
+        assert_eq!(run(set).unwrap(), Value::Null);
+        let get = r#"{ "command": "get", "species": "red", "field": "move_speed" }"#;
+        assert_eq!(run(get).unwrap(), json!(2.0));
+        let unknown =
+            r#"{ "command": "set", "species": "blue", "field": "move_speed", "value": 2 }"#;
+        assert_eq!(
+            run(unknown).unwrap_err().to_string(),
+            "unknown species blue"
+        );
+
+        run(r#"{ "command": "pause" }"#).unwrap();
+        let screenshot = run(r#"{ "command": "screenshot" }"#).unwrap();
+        let snapshot = run(r#"{ "command": "snapshot" }"#);
+        assert!(state.species_changed && !state.reset);
+        assert!(*state.paused);
+        assert_eq!(state.species[1].move_speed, 2.0);
+
+        state.species_changed = false;
+        state.handle(Command::Reset).unwrap();
+        assert!(state.species_changed && state.reset);
+        assert_eq!(state.species[1].move_speed, 3.0);
+
+        state.handle(Command::Resume).unwrap();
+        assert!(!paused);
+        assert_eq!(snapshot.unwrap_err().to_string(), "disk full");
+        let saved = saved.into_inner();
+        assert_eq!(screenshot, json!(saved[0].display().to_string()));
+        assert!(saved[0].starts_with("out"));
+    }
+}
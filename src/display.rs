@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// How `main_2` composites the per-species weights into the output texture.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// Species colors averaged by concentration, at an intensity given by their total.
+    Blend,
+    /// Species colors scaled by concentration and summed.
+    Additive,
+    /// Color of the most concentrated species only.
+    Max,
+    Viridis,
+    Magma,
+    Inferno,
+    Grayscale,
+    /// Long exposure of the agents' positions, independent of the pheromones.
+    Trails,
+}
+
+impl RenderMode {
+    const ALL: [RenderMode; 8] = [
+        RenderMode::Blend,
+        RenderMode::Additive,
+        RenderMode::Max,
+        RenderMode::Viridis,
+        RenderMode::Magma,
+        RenderMode::Inferno,
+        RenderMode::Grayscale,
+        RenderMode::Trails,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub mode: RenderMode,
+    /// Species whose concentration the scalar colormaps show, all species summed if unset.
+    pub colormap_species: Option<String>,
+    /// Color shown where there is no pheromone.
+    pub background: [u8; 3],
+    /// Concentration is multiplied by this before it is turned into intensity.
+    pub exposure: f32,
+    /// Curve applied to intensity, values above 1 fade dim trails out faster.
+    pub gamma: f32,
+    /// WGSL file providing a custom `fs_main`, relative to the settings file.
+    pub shader: Option<String>,
+    /// How fast the trails render mode fades, separate from the species' `decay_rate`.
+    pub trail_fade_rate: f32,
+    /// Brightness each agent adds to the trails per step.
+    pub trail_intensity: f32,
+    /// Overlay histograms of the agents' heritable parameters and fitness per species.
+    pub histograms: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            mode: RenderMode::Blend,
+            colormap_species: None,
+            background: [0, 0, 0],
+            exposure: 1.0,
+            gamma: 1.0,
+            shader: None,
+            trail_fade_rate: 0.5,
+            trail_intensity: 0.05,
+            histograms: false,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Display {
+    pub background: [f32; 3],
+    pub mode: u32,
+    /// Species index for the scalar colormaps, or the species count for their sum.
+    pub source: u32,
+    pub exposure: f32,
+    pub gamma: f32,
+    /// Whether `main_1` accumulates the parameter histograms.
+    pub histograms: u32,
+    /// Whether a tonemap follows, so colors may exceed one.
+    pub hdr: u32,
+    pub _p0: [u32; 3],
+}
+
+impl Display {
+    pub fn new(
+        settings: &DisplaySettings,
+        mode: RenderMode,
+        source: u32,
+        histograms: bool,
+        hdr: bool,
+    ) -> Self {
+        let [r, g, b] = settings.background;
+
+        Self {
+            background: [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0],
+            mode: mode as u32,
+            source,
+            exposure: settings.exposure,
+            gamma: settings.gamma,
+            histograms: histograms as u32,
+            hdr: hdr as u32,
+            _p0: [0; 3],
+        }
+    }
+}
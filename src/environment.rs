@@ -0,0 +1,109 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{bitmap, noise::ValueNoise};
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapSource {
+    /// The species' rate everywhere.
+    Off,
+    /// Brightness of a PNG.
+    Image,
+    /// Value noise of size `scale`.
+    Noise,
+}
+
+/// Per pixel multiplier of a species rate, from `low` at black to `high` at white.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapSettings {
+    pub source: MapSource,
+    /// PNG relative to the settings file, stretched to the window.
+    pub file: Option<String>,
+    /// Size of the noise features in pixels.
+    pub scale: f32,
+    pub low: f32,
+    pub high: f32,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            source: MapSource::Off,
+            file: None,
+            scale: 150.0,
+            low: 0.0,
+            high: 1.0,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentSettings {
+    pub decay: MapSettings,
+    pub diffusion: MapSettings,
+}
+
+impl EnvironmentSettings {
+    pub fn enabled(&self) -> bool {
+        self.decay.source != MapSource::Off || self.diffusion.source != MapSource::Off
+    }
+}
+
+/// Decay and diffusion multipliers of every pixel, row by row.
+pub fn maps(
+    settings: &EnvironmentSettings,
+    settings_dir: &Path,
+    width: u32,
+    height: u32,
+    seed: u64,
+) -> Result<Vec<[f32; 2]>> {
+    let decay = map(&settings.decay, settings_dir, width, height, seed + 1)?;
+    let diffusion = map(&settings.diffusion, settings_dir, width, height, seed + 2)?;
+
+    Ok(decay
+        .into_iter()
+        .zip(diffusion)
+        .map(|(a, b)| [a, b])
+        .collect())
+}
+
+fn map(
+    settings: &MapSettings,
+    settings_dir: &Path,
+    width: u32,
+    height: u32,
+    seed: u64,
+) -> Result<Vec<f32>> {
+    let values = match settings.source {
+        MapSource::Off => return Ok(vec![1.0; (width * height) as usize]),
+        MapSource::Image => {
+            let Some(file) = &settings.file else {
+                bail!("image map needs a file");
+            };
+            let bytes = fs::read(settings_dir.join(file))?;
+
+            bitmap::read_png(&bytes, width, height)?
+                .into_iter()
+                .map(|[r, g, b, _]| (r as f32 + g as f32 + b as f32) / (3.0 * 255.0))
+                .collect::<Vec<_>>()
+        }
+        MapSource::Noise => {
+            let noise = ValueNoise::new(width, height, settings.scale, seed);
+
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| noise.sample(x as f32, y as f32) * 0.5 + 0.5)
+                .collect()
+        }
+    };
+
+    Ok(values
+        .into_iter()
+        .map(|value| settings.low + (settings.high - settings.low) * value)
+        .collect())
+}
@@ -0,0 +1,141 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{bitmap, noise::ValueNoise};
+
+/// Source of the vector field that transports pheromone and, optionally, agents.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowMode {
+    Off,
+    /// The same `wind` everywhere.
+    Uniform,
+    /// Divergence free swirls of size `scale`.
+    Curl,
+    /// Read from `file`.
+    File,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowSettings {
+    pub mode: FlowMode,
+    /// Velocity of the uniform mode in pixels per second.
+    pub wind: [f32; 2],
+    /// Peak speed of the curl and file modes in pixels per second.
+    pub strength: f32,
+    /// Size of the curl noise swirls in pixels.
+    pub scale: f32,
+    /// A PNG whose red and green channels map 0..255 to -1..1, or raw little endian `f32` x/y
+    /// pairs for every pixel, relative to the settings file.
+    pub file: Option<String>,
+    /// Fraction of the flow added to the agents' own movement.
+    pub agent_drift: f32,
+}
+
+impl Default for FlowSettings {
+    fn default() -> Self {
+        Self {
+            mode: FlowMode::Off,
+            wind: [20.0, 0.0],
+            strength: 30.0,
+            scale: 200.0,
+            file: None,
+            agent_drift: 0.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Flow {
+    pub agent_drift: f32,
+    pub _p0: [u32; 3],
+}
+
+impl Flow {
+    pub fn new(settings: &FlowSettings) -> Self {
+        Self {
+            agent_drift: match settings.mode {
+                FlowMode::Off => 0.0,
+                _ => settings.agent_drift,
+            },
+            _p0: [0; 3],
+        }
+    }
+}
+
+/// Builds the velocity of every pixel, row by row.
+pub fn field(
+    settings: &FlowSettings,
+    settings_dir: &Path,
+    width: u32,
+    height: u32,
+    seed: u64,
+) -> Result<Vec<[f32; 2]>> {
+    let pixels = (width * height) as usize;
+
+    match settings.mode {
+        FlowMode::Off => Ok(vec![[0.0; 2]; pixels]),
+        FlowMode::Uniform => Ok(vec![settings.wind; pixels]),
+        FlowMode::Curl => Ok(curl(settings, width, height, seed)),
+        FlowMode::File => {
+            let Some(file) = &settings.file else {
+                bail!("flow mode file needs a file");
+            };
+            let path = settings_dir.join(file);
+            let bytes = fs::read(&path)?;
+
+            if path.extension().is_some_and(|extension| extension == "png") {
+                let component = |value: u8| (value as f32 / 255.0 * 2.0 - 1.0) * settings.strength;
+                Ok(bitmap::read_png(&bytes, width, height)?
+                    .into_iter()
+                    .map(|[r, g, ..]| [component(r), component(g)])
+                    .collect())
+            } else {
+                if bytes.len() != pixels * 2 * 4 {
+                    bail!(
+                        "{} does not hold {width}x{height} flow vectors",
+                        path.display()
+                    );
+                }
+                Ok(bytes
+                    .chunks_exact(8)
+                    .map(|chunk| {
+                        let x = f32::from_le_bytes(chunk[..4].try_into().unwrap());
+                        let y = f32::from_le_bytes(chunk[4..].try_into().unwrap());
+                        [x, y]
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Curl of value noise, normalized to a peak speed of `strength`.
+fn curl(settings: &FlowSettings, width: u32, height: u32, seed: u64) -> Vec<[f32; 2]> {
+    let noise = ValueNoise::new(width, height, settings.scale, seed);
+
+    let mut field = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+            let dx = noise.sample(x + 0.5, y) - noise.sample(x - 0.5, y);
+            let dy = noise.sample(x, y + 0.5) - noise.sample(x, y - 0.5);
+            field.push([dy, -dx]);
+        }
+    }
+
+    let peak = field
+        .iter()
+        .map(|[x, y]| (x * x + y * y).sqrt())
+        .fold(f32::EPSILON, f32::max);
+    for velocity in &mut field {
+        velocity[0] *= settings.strength / peak;
+        velocity[1] *= settings.strength / peak;
+    }
+
+    field
+}
@@ -0,0 +1,63 @@
+@group(0)
+@binding(0)
+var<storage, read> species: array<Species>;
+
+// The lifecycle buffer of the compute shader, see `histograms_offset` there.
+@group(0)
+@binding(1)
+var<storage, read> lifecycle: array<u32>;
+
+const POPULATIONS: u32 = 2u;
+const PARAMETERS: u32 = 5u;
+const BINS: u32 = 32u;
+
+const MARGIN: f32 = 0.02;
+const HEIGHT: f32 = 0.4;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+// One instance per bar, ordered by species, parameter and bin, with one panel per parameter
+// along the bottom of the screen.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let bin = instance % BINS;
+    let histogram = instance / BINS;
+    let parameter = histogram % PARAMETERS;
+    let species_index = histogram / PARAMETERS;
+
+    let offset = POPULATIONS + arrayLength(&species) + histogram * BINS;
+    var peak = 1u;
+    for (var i = 0u; i < BINS; i++) {
+        peak = max(peak, lifecycle[offset + i]);
+    }
+    let height = f32(lifecycle[offset + bin]) / f32(peak);
+
+    let panel_width = 2.0 / f32(PARAMETERS);
+    let left = -1.0 + f32(parameter) * panel_width + MARGIN;
+    let bar_width = (panel_width - 2.0 * MARGIN) / f32(BINS);
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    let x = left + (f32(bin) + corner.x) * bar_width;
+    let y = -1.0 + MARGIN + corner.y * height * (HEIGHT - MARGIN);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(x, y, 0.0, 1.0);
+    out.color = vec4<f32>(species[species_index].color, 0.6);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
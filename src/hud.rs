@@ -0,0 +1,169 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+pub const TITLE: &str = "pheromone_simulation";
+
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Names of the compute passes timed by `GpuTimer`, in dispatch order: moving the agents along with
+/// their deaths and births, drawing the field, then advecting, diffusing and decaying it.
+pub const PASSES: [&str; 3] = ["agents", "display", "field"];
+
+/// Measures each compute pass with timestamp queries, when the adapter supports them.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    period: f32,
+}
+
+impl GpuTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let count = PASSES.len() as u32 * 2;
+        let size = count as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: None,
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+        })
+    }
+
+    pub fn writes(&self, pass: usize) -> wgpu::ComputePassTimestampWrites<'_> {
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(pass as u32 * 2),
+            end_of_pass_write_index: Some(pass as u32 * 2 + 1),
+        }
+    }
+
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..PASSES.len() as u32 * 2,
+            &self.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+    }
+
+    /// Milliseconds spent in each pass of the last submitted frame.
+    pub fn read(&self, device: &wgpu::Device) -> Result<[f32; 3]> {
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.readback_buffer.unmap();
+
+        let mut times = [0.0; 3];
+        for (time, pair) in times.iter_mut().zip(timestamps.chunks_exact(2)) {
+            *time = pair[1].wrapping_sub(pair[0]) as f32 * self.period / 1_000_000.0;
+        }
+
+        Ok(times)
+    }
+}
+
+/// Frame and step counters summarized into the window title.
+pub struct Hud {
+    last_update: Instant,
+    frames: u32,
+    steps: u32,
+    total_steps: u64,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self {
+            last_update: Instant::now(),
+            frames: 0,
+            steps: 0,
+            total_steps: 0,
+        }
+    }
+
+    pub fn frame(&mut self, steps: u32) {
+        self.frames += 1;
+        self.steps += steps;
+        self.total_steps += steps as u64;
+    }
+
+    /// Starts counting steps from zero again, keeping the frame rate.
+    pub fn reset_steps(&mut self) {
+        self.steps = 0;
+        self.total_steps = 0;
+    }
+
+    /// Returns whether enough time passed since the last title update.
+    pub fn due(&self) -> bool {
+        self.last_update.elapsed() >= UPDATE_INTERVAL
+    }
+
+    pub fn title(&mut self, populations: &[(&str, u32)], pass_times: Option<[f32; 3]>) -> String {
+        let elapsed = self.last_update.elapsed().as_secs_f32();
+
+        let mut title = format!(
+            "{TITLE} | {:.0} fps | {:.0} steps/s | {} steps |",
+            self.frames as f32 / elapsed,
+            self.steps as f32 / elapsed,
+            self.total_steps,
+        );
+
+        for (name, population) in populations {
+            let _ = write!(title, " {name} {population}");
+        }
+
+        if let Some(pass_times) = pass_times {
+            title.push_str(" |");
+            for (name, time) in PASSES.iter().zip(pass_times) {
+                let _ = write!(title, " {name} {time:.2} ms");
+            }
+        }
+
+        self.last_update = Instant::now();
+        self.frames = 0;
+        self.steps = 0;
+
+        title
+    }
+}
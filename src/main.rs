@@ -1,11 +1,66 @@
 #![windows_subsystem = "windows"]
 
-use std::{collections::HashMap, fs, iter, mem, time::Instant};
+/// Like `include_wgsl!`, with the `Species` struct from species.wgsl prepended.
+macro_rules! include_species_wgsl {
+    ($path:literal) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($path),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("species.wgsl"), "\n", include_str!($path)).into(),
+            ),
+        }
+    };
+}
 
-use anyhow::Result;
-use rand::Rng;
+mod agent_render;
+mod bitmap;
+mod control;
+mod display;
+mod environment;
+mod flow;
+mod histogram;
+mod hud;
+mod motion;
+mod noise;
+mod osc;
+mod postprocess;
+mod readback;
+mod recording;
+mod render_shader;
+mod screenshot;
+mod script;
+mod sensor;
+mod snapshot;
+mod steering;
+mod timeline;
+mod trails;
+mod volume;
+
+use std::{collections::HashMap, env, fs, iter, mem, path::Path, sync::Arc, time::Instant};
+
+use agent_render::{AgentRenderSettings, AgentRenderer};
+use anyhow::{bail, Result};
+use control::{Control, ControlSettings};
+use display::{Display, DisplaySettings, RenderMode};
+use environment::EnvironmentSettings;
+use flow::{Flow, FlowMode, FlowSettings};
+use histogram::HistogramRenderer;
+use hud::{GpuTimer, Hud};
+use motion::Motion;
+use osc::{Osc, OscSettings};
+use postprocess::{Bloom, Post, PostProcessSettings, Tonemap};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use recording::{Recorder, RecordingSettings};
+use render_shader::{Frame, ShaderWatcher};
+use script::Script;
+use sensor::{SensorShape, SensorWeighting};
 use serde::{Deserialize, Serialize};
-use wgpu::{include_wgsl, util::DeviceExt, StoreOp};
+use snapshot::Snapshot;
+use steering::Steering;
+use timeline::{Timeline, TrackSettings};
+use trails::Trails;
+use volume::VolumeSettings;
+use wgpu::{util::DeviceExt, StoreOp};
 use winit::{
     dpi::PhysicalSize,
     event::{ElementState, Event, KeyEvent, WindowEvent},
@@ -14,6 +69,11 @@ use winit::{
     window::{Fullscreen, WindowBuilder},
 };
 
+const SETTINGS_PATH: &str = "settings.toml";
+
+/// Index of the compute pass drawing the field into the texture, the only one run while paused.
+const DISPLAY_PASS: usize = 1;
+
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
@@ -27,8 +87,22 @@ struct Agent {
     position: [f32; 2],
     angle: f32,
     species: u32,
+    move_speed_scale: f32,
+    turn_speed_scale: f32,
+    sensor_angle_scale: f32,
+    sensor_offset_scale: f32,
+    energy: f32,
+    alive: u32,
+    /// Energy gained from pheromone since birth.
+    fitness: f32,
+    /// One plus the pixel an exclusive agent claims in the occupancy grid, zero if none.
+    claim: u32,
 }
 
+// Must match the stride of `Agent` in compute.wgsl.
+const _: () = assert!(mem::size_of::<Agent>() == 48);
+
+/// Laid out like `Species` in species.wgsl, which every shader reading it shares.
 #[repr(C, align(16))]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct Species {
@@ -45,6 +119,22 @@ struct Species {
     like_length: u32,
     hate_index: u32,
     hate_length: u32,
+    initial_energy: f32,
+    drain_rate: f32,
+    gain_rate: f32,
+    reproduce_energy: f32,
+    convert_to: u32,
+    convert_threshold: f32,
+    convert_probability: f32,
+    mutation: f32,
+    sensors: u32,
+    sensor_shape: u32,
+    sensor_weighting: u32,
+    sensor_bilinear: u32,
+    steering: u32,
+    steering_temperature: f32,
+    crowding_threshold: f32,
+    exclusive: u32,
     _p1: u32,
 }
 
@@ -53,6 +143,45 @@ struct Settings {
     width: u32,
     height: u32,
     fullscreen: bool,
+    seed: Option<u64>,
+    /// Agent slots, defaults to the sum of all `amount`s. Extra slots start out free for births.
+    capacity: Option<u32>,
+    /// Cells along z of a volumetric simulation, which replaces the 2D one when set.
+    depth: Option<u32>,
+    #[serde(default)]
+    volume: VolumeSettings,
+    /// Show frame rate, step counts, populations and GPU timings in the window title.
+    #[serde(default = "default_true")]
+    hud: bool,
+    #[serde(default = "default_true")]
+    screenshot_metadata: bool,
+    /// Whether agents move along their heading after or before this step's turn.
+    #[serde(default)]
+    motion: Motion,
+    #[serde(default)]
+    recording: RecordingSettings,
+    #[serde(default)]
+    display: DisplaySettings,
+    #[serde(default)]
+    postprocess: PostProcessSettings,
+    #[serde(default)]
+    agents: AgentRenderSettings,
+    #[serde(default)]
+    flow: FlowSettings,
+    /// Per pixel multipliers of the species' decay and diffusion rates.
+    #[serde(default)]
+    environment: EnvironmentSettings,
+    /// Keyframed species parameters, applied every step over simulated time.
+    #[serde(default)]
+    timeline: Vec<TrackSettings>,
+    /// Rhai file relative to the settings file, whose hooks run on start, every step and on keys.
+    script: Option<String>,
+    /// Localhost HTTP server taking JSON commands, see README.adoc.
+    #[serde(default)]
+    control: ControlSettings,
+    /// Open Sound Control messages over UDP setting species fields, see README.adoc.
+    #[serde(default)]
+    osc: OscSettings,
     species: Vec<SpeciesSettings>,
 }
 
@@ -68,8 +197,67 @@ struct SpeciesSettings {
     sensor_angle: f32,
     sensor_offset: f32,
     sensor_size: u32,
+    /// Odd number of sensors, spread evenly between `-sensor_angle` and `sensor_angle`.
+    #[serde(default = "sensor::default_sensors")]
+    sensors: u32,
+    #[serde(default)]
+    sensor_shape: SensorShape,
+    #[serde(default)]
+    sensor_weighting: SensorWeighting,
+    /// Sample the pheromone between pixels instead of at the pixel the sensor falls into.
+    #[serde(default)]
+    sensor_bilinear: bool,
+    #[serde(default)]
+    steering: Steering,
+    /// Lower values make softmax steering pick the best sensor more reliably.
+    #[serde(default = "steering::default_temperature")]
+    steering_temperature: f32,
+    /// Forward reading above which crowding avoiding agents turn away from the best side.
+    #[serde(default = "steering::default_crowding_threshold")]
+    crowding_threshold: f32,
+    /// Agents only move into pixels no other exclusive agent is in, otherwise they turn randomly.
+    #[serde(default)]
+    exclusive: bool,
     decay_rate: f32,
     diffuse_rate: f32,
+    /// Each agent's parameters are scaled by a random factor in `1 ± jitter`, picked at spawn.
+    /// Jitters range from 0 to 1.
+    #[serde(default)]
+    move_speed_jitter: f32,
+    #[serde(default)]
+    turn_speed_jitter: f32,
+    #[serde(default)]
+    sensor_angle_jitter: f32,
+    #[serde(default)]
+    sensor_offset_jitter: f32,
+    /// Agents of species without a lifecycle never die or reproduce.
+    lifecycle: Option<LifecycleSettings>,
+    /// Species an agent turns into when the average concentration of that species' pheromone
+    /// around it is at least `convert_threshold`, with a chance of `convert_probability` per second.
+    convert_to: Option<String>,
+    #[serde(default)]
+    convert_threshold: f32,
+    #[serde(default)]
+    convert_probability: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LifecycleSettings {
+    initial_energy: f32,
+    /// Energy lost per second.
+    drain_rate: f32,
+    /// Energy gained per second per unit of liked pheromone sensed ahead.
+    gain_rate: f32,
+    /// Energy at which an agent splits its energy with a child in a free slot.
+    reproduce_energy: f32,
+    /// Children inherit their parent's parameter scales, each changed by a random factor in
+    /// `1 ± mutation`.
+    #[serde(default)]
+    mutation: f32,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 fn main() -> Result<()> {
@@ -77,10 +265,30 @@ fn main() -> Result<()> {
 }
 
 async fn run() -> Result<()> {
-    let settings: Settings = toml::from_str(&fs::read_to_string("settings.toml").unwrap()).unwrap();
+    let snapshot = env::args()
+        .nth(1)
+        .map(|path| Snapshot::read(Path::new(&path)))
+        .transpose()?;
+
+    let settings_toml = match &snapshot {
+        Some(snapshot) => snapshot.settings.clone(),
+        None => fs::read_to_string(SETTINGS_PATH).unwrap(),
+    };
+    let mut settings: Settings = toml::from_str(&settings_toml).unwrap();
+    let settings_dir = Path::new(SETTINGS_PATH).parent().unwrap_or(Path::new(""));
+
+    let seed = match &snapshot {
+        Some(snapshot) => snapshot.seed,
+        // TOML integers are signed, so the resolved settings only hold seeds up to `i64::MAX`.
+        None => settings.seed.unwrap_or_else(|| rand::random::<u64>() >> 1),
+    };
+    settings.seed = Some(seed);
+    let resolved_settings = toml::to_string(&settings)?;
 
     let event_loop = EventLoop::new()?;
-    let mut window_builder = WindowBuilder::new().with_resizable(false);
+    let mut window_builder = WindowBuilder::new()
+        .with_title(hud::TITLE)
+        .with_resizable(false);
 
     if settings.fullscreen {
         window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
@@ -112,13 +320,16 @@ async fn run() -> Result<()> {
         .request_device(
             &wgpu::DeviceDescriptor {
                 required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                    | wgpu::Features::FLOAT32_FILTERABLE,
+                    | wgpu::Features::FLOAT32_FILTERABLE
+                    | (adapter.features() & wgpu::Features::TIMESTAMP_QUERY),
                 ..Default::default()
             },
             None,
         )
         .await
         .unwrap();
+    // Shared with scripts, whose functions read back and write buffers whenever they are called.
+    let (device, queue) = (Arc::new(device), Arc::new(queue));
 
     let config = surface.get_default_config(&adapter, width, height).unwrap();
     surface.configure(&device, &config);
@@ -126,16 +337,46 @@ async fn run() -> Result<()> {
     let width = settings.width;
     let height = settings.height;
 
-    let species_settings = settings.species;
+    let species_settings = &settings.species;
     let mut species_map = HashMap::new();
 
     for (i, species_setting) in species_settings.iter().enumerate() {
+        if species_setting.sensors.is_multiple_of(2) {
+            bail!(
+                "species {} needs an odd number of sensors",
+                species_setting.name
+            );
+        }
+
+        if species_setting.steering == Steering::Softmax
+            && species_setting.steering_temperature <= 0.0
+        {
+            bail!(
+                "species {} needs a positive steering_temperature for softmax steering",
+                species_setting.name
+            );
+        }
+
+        for (name, jitter) in [
+            ("move_speed_jitter", species_setting.move_speed_jitter),
+            ("turn_speed_jitter", species_setting.turn_speed_jitter),
+            ("sensor_angle_jitter", species_setting.sensor_angle_jitter),
+            ("sensor_offset_jitter", species_setting.sensor_offset_jitter),
+        ] {
+            if !(0.0..=1.0).contains(&jitter) {
+                bail!(
+                    "species {} needs a {name} between 0 and 1",
+                    species_setting.name
+                );
+            }
+        }
+
         species_map.insert(species_setting.name.to_string(), i);
     }
 
     let mut relations = Vec::new();
 
-    let species = species_settings
+    let mut species = species_settings
         .iter()
         .map(|st| {
             let r = st.color[0] as f32 / 255.0;
@@ -158,6 +399,14 @@ async fn run() -> Result<()> {
             let like_length = hate_index - like_index;
             let hate_length = relations.len() as u32 - hate_index;
 
+            let lifecycle = st.lifecycle.as_ref().unwrap_or(&LifecycleSettings {
+                initial_energy: 1.0,
+                drain_rate: 0.0,
+                gain_rate: 0.0,
+                reproduce_energy: 0.0,
+                mutation: 0.0,
+            });
+
             Species {
                 color,
                 _p0: 0,
@@ -172,42 +421,128 @@ async fn run() -> Result<()> {
                 like_length,
                 hate_index,
                 hate_length,
+                initial_energy: lifecycle.initial_energy,
+                drain_rate: lifecycle.drain_rate,
+                gain_rate: lifecycle.gain_rate,
+                reproduce_energy: lifecycle.reproduce_energy,
+                convert_to: st
+                    .convert_to
+                    .as_ref()
+                    .map_or(u32::MAX, |name| *species_map.get(name).unwrap() as u32),
+                convert_threshold: st.convert_threshold,
+                convert_probability: st.convert_probability,
+                mutation: lifecycle.mutation,
+                sensors: st.sensors,
+                sensor_shape: st.sensor_shape as u32,
+                sensor_weighting: st.sensor_weighting as u32,
+                sensor_bilinear: st.sensor_bilinear as u32,
+                steering: st.steering as u32,
+                steering_temperature: st.steering_temperature,
+                crowding_threshold: st.crowding_threshold,
+                exclusive: st.exclusive as u32,
                 _p1: 0,
             }
         })
         .collect::<Vec<_>>();
 
+    if let Some(snapshot) = &snapshot {
+        if settings.depth.is_some() {
+            bail!("snapshots only continue 2D runs, but the snapshot's settings set a depth");
+        }
+
+        // The species and relations as they were when saved, including changes made at runtime.
+        if snapshot.species.len() != species.len() * mem::size_of::<Species>() {
+            bail!("snapshot species do not match its settings");
+        }
+        species = snapshot
+            .species
+            .chunks_exact(mem::size_of::<Species>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+        relations = snapshot
+            .relations
+            .chunks_exact(mem::size_of::<u32>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+    }
+
+    if let Some(depth) = settings.depth {
+        return volume::run(
+            event_loop,
+            &window,
+            &surface,
+            &device,
+            &queue,
+            config.format,
+            &settings,
+            depth,
+            &species,
+            &relations,
+            seed,
+        );
+    }
+
     let radius = height as f32 * 0.4;
     let center_x = width as f32 / 2.0;
     let center_y = height as f32 / 2.0;
 
-    let agents = species_settings
-        .iter()
-        .flat_map(|s| {
-            (0..s.amount).map(|_| {
-                let mut rng = rand::thread_rng();
-
-                let (x, y) = loop {
-                    let x = rng.gen_range((center_x - radius)..(center_x + radius));
-                    let y = rng.gen_range((center_y - radius)..(center_y + radius));
-
-                    if (x - center_x) * (x - center_x) + (y - center_y) * (y - center_y)
-                        <= radius * radius
-                    {
-                        break (x, y);
-                    }
-                };
-
-                let angle = (center_y - y).atan2(center_x - x);
+    let agents = match &snapshot {
+        Some(snapshot) => {
+            if snapshot.agents.len() % mem::size_of::<Agent>() != 0 {
+                bail!("snapshot agent buffer is not a whole number of agents");
+            }
 
-                Agent {
-                    position: [x, y],
-                    angle,
-                    species: *species_map.get(&s.name).unwrap() as u32,
+            snapshot
+                .agents
+                .chunks_exact(mem::size_of::<Agent>())
+                .map(bytemuck::pod_read_unaligned)
+                // The occupancy grid isn't saved, so exclusive agents claim their pixels anew.
+                .map(|agent: Agent| Agent { claim: 0, ..agent })
+                .collect::<Vec<Agent>>()
+        }
+        None => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut agents = Vec::new();
+
+            for (s, spec) in species_settings.iter().zip(&species) {
+                for _ in 0..s.amount {
+                    let (x, y) = loop {
+                        let x = rng.gen_range((center_x - radius)..(center_x + radius));
+                        let y = rng.gen_range((center_y - radius)..(center_y + radius));
+
+                        if (x - center_x) * (x - center_x) + (y - center_y) * (y - center_y)
+                            <= radius * radius
+                        {
+                            break (x, y);
+                        }
+                    };
+
+                    let angle = (center_y - y).atan2(center_x - x);
+
+                    let mut scale = |jitter: f32| 1.0 + rng.gen_range(-jitter..=jitter);
+
+                    agents.push(Agent {
+                        position: [x, y],
+                        angle,
+                        species: *species_map.get(&s.name).unwrap() as u32,
+                        move_speed_scale: scale(s.move_speed_jitter),
+                        turn_speed_scale: scale(s.turn_speed_jitter),
+                        sensor_angle_scale: scale(s.sensor_angle_jitter),
+                        sensor_offset_scale: scale(s.sensor_offset_jitter),
+                        energy: spec.initial_energy,
+                        alive: 1,
+                        fitness: 0.0,
+                        claim: 0,
+                    });
                 }
-            })
-        })
-        .collect::<Vec<_>>();
+            }
+
+            let capacity = settings.capacity.unwrap_or(0) as usize;
+            agents.resize(capacity.max(agents.len()), bytemuck::Zeroable::zeroed());
+
+            agents
+        }
+    };
 
     let (x, y, z) = {
         let len = agents.len() as u32;
@@ -268,31 +603,140 @@ async fn run() -> Result<()> {
 
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    let species_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    let species_buffer = Arc::new(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&species),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        }),
+    );
+
+    let timeline = Timeline::new(&settings.timeline, &species_map)?;
+    let start_time = snapshot.as_ref().map_or(0.0, |snapshot| snapshot.time);
+    let mut simulated_time = start_time;
+
+    // Room for scripts to make every species like and hate every other one.
+    let mut relations_padded = relations.clone();
+    relations_padded.resize(relations.len().max(2 * species.len() * species.len()), 0);
+    let relations_buffer = Arc::new(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&relations_padded),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        }),
+    );
+
+    let agents_buffer = Arc::new(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&agents),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::VERTEX,
+        }),
+    );
+
+    let weights_len = width as usize * height as usize * species.len();
+    let weights = match &snapshot {
+        Some(snapshot) => {
+            if snapshot.weights.len() != weights_len * mem::size_of::<f32>() {
+                bail!("snapshot weight buffer does not match its settings");
+            }
+
+            snapshot.weights.clone()
+        }
+        None => bytemuck::cast_slice(&vec![0.0f32; weights_len]).to_vec(),
+    };
+
+    let weights_buffer = Arc::new(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &weights,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        }),
+    );
+
+    let lifecycle_enabled = species_settings.iter().any(|s| s.lifecycle.is_some());
+    let lifecycle_counters =
+        2 + species.len() + species.len() * histogram::PARAMETERS.len() * histogram::BINS;
+
+    let lifecycle_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&species),
+        size: ((lifecycle_counters + 2 * agents.len()) * mem::size_of::<u32>())
+            as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    }));
+
+    let flow_enabled = settings.flow.mode != FlowMode::Off;
+    let (flow_width, flow_height) = if flow_enabled {
+        (width, height)
+    } else {
+        (1, 1)
+    };
+    let flow_field = flow::field(&settings.flow, settings_dir, flow_width, flow_height, seed)?;
+    let flow_view = bitmap::texture(&device, &queue, &flow_field, flow_width, flow_height);
+
+    let (environment_width, environment_height) = if settings.environment.enabled() {
+        (width, height)
+    } else {
+        (1, 1)
+    };
+    let environment_maps = environment::maps(
+        &settings.environment,
+        settings_dir,
+        environment_width,
+        environment_height,
+        seed,
+    )?;
+    let environment_view = bitmap::texture(
+        &device,
+        &queue,
+        &environment_maps,
+        environment_width,
+        environment_height,
+    );
+
+    let advected_len = if flow_enabled { weights_len } else { 1 };
+    let advected_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: (advected_len * mem::size_of::<f32>()) as wgpu::BufferAddress,
         usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
     });
 
-    let relations_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    let exclusive = species_settings.iter().any(|s| s.exclusive);
+    let occupancy_len = if exclusive {
+        width as usize * height as usize
+    } else {
+        1
+    };
+    let occupancy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&relations),
-        usage: wgpu::BufferUsages::STORAGE,
+        size: (occupancy_len * mem::size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
     });
 
-    let agents_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    let motion_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&agents),
-        usage: wgpu::BufferUsages::STORAGE,
+        contents: bytemuck::cast_slice(&[settings.motion as u32]),
+        usage: wgpu::BufferUsages::UNIFORM,
     });
 
-    let weights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    let flow_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&vec![
-            0.0f32;
-            width as usize * height as usize * species.len()
-        ]),
-        usage: wgpu::BufferUsages::STORAGE,
+        contents: bytemuck::cast_slice(&[Flow::new(&settings.flow)]),
+        usage: wgpu::BufferUsages::UNIFORM,
     });
 
     let time_delta_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -302,112 +746,36 @@ async fn run() -> Result<()> {
         mapped_at_creation: false,
     });
 
-    let compute_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::ReadWrite,
-                        format: wgpu::TextureFormat::Rgba32Float,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 5,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 6,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 7,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 8,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 9,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
+    let mut histograms = settings.display.histograms;
+    let mut render_mode = settings.display.mode;
+    let mut colormap_source = match &settings.display.colormap_species {
+        Some(name) => *species_map.get(name).unwrap() as u32,
+        None => species.len() as u32,
+    };
+
+    let mut tonemap = settings.postprocess.tonemap;
+    let display_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&[Display::new(
+            &settings.display,
+            render_mode,
+            colormap_source,
+            histograms,
+            tonemap != Tonemap::None,
+        )]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let trails = Trails::new(
+        &device,
+        &species_buffer,
+        width,
+        height,
+        settings.display.trail_fade_rate,
+        settings.display.trail_intensity,
+    );
+
+    let compute_bind_group_layout = compute_bind_group_layout(&device);
 
     let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
@@ -453,10 +821,46 @@ async fn run() -> Result<()> {
                 binding: 9,
                 resource: time_delta_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: display_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::TextureView(trails.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: lifecycle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::TextureView(&flow_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: advected_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: flow_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: wgpu::BindingResource::TextureView(&environment_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 17,
+                resource: motion_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 18,
+                resource: occupancy_buffer.as_entire_binding(),
+            },
         ],
     });
 
-    let compute_shader = device.create_shader_module(include_wgsl!("compute.wgsl"));
+    let compute_shader = device.create_shader_module(include_species_wgsl!("compute.wgsl"));
 
     let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
@@ -472,6 +876,22 @@ async fn run() -> Result<()> {
             entry_point: "main_1",
         });
 
+    let main_free_compute_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "main_free",
+        });
+
+    let main_birth_compute_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "main_birth",
+        });
+
     let main_2_compute_pipeline =
         device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: None,
@@ -480,6 +900,22 @@ async fn run() -> Result<()> {
             entry_point: "main_2",
         });
 
+    let main_advect_compute_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "main_advect",
+        });
+
+    let main_advect_store_compute_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "main_advect_store",
+        });
+
     let main_3_compute_pipeline =
         device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: None,
@@ -488,7 +924,13 @@ async fn run() -> Result<()> {
             entry_point: "main_3",
         });
 
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
 
     let vertices: &[Vertex] = &[
         Vertex {
@@ -523,6 +965,22 @@ async fn run() -> Result<()> {
         usage: wgpu::BufferUsages::INDEX,
     });
 
+    let mut bloom_enabled = settings.postprocess.bloom;
+    let bloom = Bloom::new(&device, &view, width, height, &settings.postprocess);
+
+    let post_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&[Post::new(&settings.postprocess, bloom_enabled, tonemap)]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let frame_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: mem::size_of::<Frame>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
     let render_bind_group_layout =
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
@@ -543,6 +1001,56 @@ async fn run() -> Result<()> {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -558,68 +1066,260 @@ async fn run() -> Result<()> {
                 binding: 1,
                 resource: wgpu::BindingResource::Sampler(&sampler),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(bloom.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: post_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: weights_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: species_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: frame_buffer.as_entire_binding(),
+            },
         ],
     });
 
-    let render_shader = device.create_shader_module(include_wgsl!("render.wgsl"));
-
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
         bind_group_layouts: &[&render_bind_group_layout],
         push_constant_ranges: &[],
     });
 
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    let create_render_pipeline = |source: &str| -> Result<wgpu::RenderPipeline> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &render_shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &render_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            bail!("{err}");
+        }
+
+        Ok(render_pipeline)
+    };
+
+    let mut render_pipeline = create_render_pipeline(&render_shader::builtin())?;
+    let mut shader_watcher = settings
+        .display
+        .shader
+        .as_ref()
+        .map(|path| ShaderWatcher::new(settings_dir.join(path)));
+
+    let mut agent_mode = settings.agents.mode;
+    let agent_renderer = AgentRenderer::new(
+        &device,
+        config.format,
+        wgpu::BlendState::REPLACE,
+        &species_buffer,
+        [width as f32, height as f32],
+        settings.agents.size,
+        1.0,
+    );
+
+    let histogram_renderer = HistogramRenderer::new(
+        &device,
+        config.format,
+        &species_buffer,
+        &lifecycle_buffer,
+        species.len(),
+    );
+
+    // Every frame is also drawn here, so screenshots and recordings match what is presented,
+    // post-processing included.
+    let capture = device.create_texture(&wgpu::TextureDescriptor {
         label: None,
-        layout: Some(&render_pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &render_shader,
-            entry_point: "vs_main",
-            buffers: &[wgpu::VertexBufferLayout {
-                array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &[
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        shader_location: 0,
-                        format: wgpu::VertexFormat::Float32x2,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                        shader_location: 1,
-                        format: wgpu::VertexFormat::Float32x2,
-                    },
-                ],
-            }],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &render_shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
         },
-        multiview: None,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
     });
+    let capture_view = capture.create_view(&wgpu::TextureViewDescriptor::default());
+    let read_capture = || {
+        let texels = readback::read_texture(&device, &queue, &capture)?;
+        screenshot::to_rgba8(&texels, config.format)
+    };
+
+    let mut agent_dispatches = vec![(&main_1_compute_pipeline, (x, y, z))];
+    if lifecycle_enabled {
+        agent_dispatches.push((&main_free_compute_pipeline, (x, y, z)));
+        agent_dispatches.push((&main_birth_compute_pipeline, (x, y, z)));
+    }
+
+    let mut field_dispatches = Vec::new();
+    if flow_enabled {
+        field_dispatches.push((&main_advect_compute_pipeline, (width, height, 1)));
+        field_dispatches.push((&main_advect_store_compute_pipeline, (width, height, 1)));
+    }
+    field_dispatches.push((&main_3_compute_pipeline, (width, height, 1)));
+
+    let passes = [
+        agent_dispatches,
+        vec![(&main_2_compute_pipeline, (width, height, 1))],
+        field_dispatches,
+    ];
+
+    let gpu_timer = GpuTimer::new(&device, &queue);
+    let mut hud = Hud::new();
+
+    let mut script = settings
+        .script
+        .as_ref()
+        .map(|file| {
+            let gpu = script::Gpu {
+                device: device.clone(),
+                queue: queue.clone(),
+                species: species_buffer.clone(),
+                relations: relations_buffer.clone(),
+                agents: agents_buffer.clone(),
+                weights: weights_buffer.clone(),
+                lifecycle: lifecycle_buffer.clone(),
+            };
+            Script::load(
+                &settings_dir.join(file),
+                gpu,
+                width,
+                height,
+                &species_map,
+                &species,
+                &relations,
+                seed,
+            )
+        })
+        .transpose()?;
+
+    if let Some(script) = script.as_mut() {
+        script.on_start(&mut species)?;
+    }
+
+    let control = settings
+        .control
+        .enabled
+        .then(|| Control::start(settings.control.port))
+        .transpose()?;
+    if let Some(control) = &control {
+        println!(
+            "control server listening on http://127.0.0.1:{}",
+            control.port()
+        );
+    }
+
+    let mut osc = settings
+        .osc
+        .enabled
+        .then(|| Osc::start(&settings.osc, &species_map))
+        .transpose()?;
+    if let Some(osc) = &osc {
+        println!("listening for OSC on {}:{}", settings.osc.host, osc.port());
+    }
+
+    let save_snapshot = |path: &Path, time: f32| {
+        let species = readback::read_buffer(&device, &queue, &species_buffer)?;
+        let relations = readback::read_buffer(&device, &queue, &relations_buffer)?;
+        let agents = readback::read_buffer(&device, &queue, &agents_buffer)?;
+        let weights = readback::read_buffer(&device, &queue, &weights_buffer)?;
+
+        Snapshot {
+            seed,
+            time,
+            settings: resolved_settings.clone(),
+            species,
+            relations,
+            agents,
+            weights,
+        }
+        .write(path)
+    };
+
+    let save_screenshot = |path: &Path| {
+        let metadata = settings
+            .screenshot_metadata
+            .then_some(resolved_settings.as_str());
+
+        let pixels = read_capture()?;
+        screenshot::write_png(path, &pixels, config.width, config.height, metadata)
+    };
+
+    // Reset returns to the state after `on_start`, including relations the script changed.
+    let initial_species = species.clone();
+    let mut initial_relations = script
+        .as_ref()
+        .map_or_else(|| relations.clone(), Script::relations);
+    initial_relations.resize(relations_padded.len(), 0);
+    let mut paused = false;
 
     let mut start = Instant::now();
+    let started = Instant::now();
+    let mut recorder: Option<Recorder> = None;
+    let mut step: u64 = 0;
 
     event_loop.run(|event, target| match event {
         Event::WindowEvent {
@@ -636,30 +1336,244 @@ async fn run() -> Result<()> {
                 },
             window_id,
         } if window_id == window.id() => target.exit(),
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            state: ElementState::Pressed,
+                            physical_key: PhysicalKey::Code(key_code),
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                },
+            window_id,
+        } if window_id == window.id() => match key_code {
+            KeyCode::KeyM => {
+                render_mode = render_mode.next();
+                let display = Display::new(
+                    &settings.display,
+                    render_mode,
+                    colormap_source,
+                    histograms,
+                    tonemap != Tonemap::None,
+                );
+                queue.write_buffer(&display_buffer, 0, bytemuck::cast_slice(&[display]));
+            }
+            KeyCode::KeyN => {
+                colormap_source = (colormap_source + 1) % (species.len() as u32 + 1);
+                let display = Display::new(
+                    &settings.display,
+                    render_mode,
+                    colormap_source,
+                    histograms,
+                    tonemap != Tonemap::None,
+                );
+                queue.write_buffer(&display_buffer, 0, bytemuck::cast_slice(&[display]));
+            }
+            KeyCode::KeyE => {
+                histograms = !histograms;
+                let display = Display::new(
+                    &settings.display,
+                    render_mode,
+                    colormap_source,
+                    histograms,
+                    tonemap != Tonemap::None,
+                );
+                queue.write_buffer(&display_buffer, 0, bytemuck::cast_slice(&[display]));
+            }
+            KeyCode::KeyA => agent_mode = agent_mode.next(),
+            KeyCode::KeyB => {
+                bloom_enabled = !bloom_enabled;
+                let post = Post::new(&settings.postprocess, bloom_enabled, tonemap);
+                queue.write_buffer(&post_buffer, 0, bytemuck::cast_slice(&[post]));
+            }
+            KeyCode::KeyT => {
+                tonemap = tonemap.next();
+                let post = Post::new(&settings.postprocess, bloom_enabled, tonemap);
+                queue.write_buffer(&post_buffer, 0, bytemuck::cast_slice(&[post]));
+                let display = Display::new(
+                    &settings.display,
+                    render_mode,
+                    colormap_source,
+                    histograms,
+                    tonemap != Tonemap::None,
+                );
+                queue.write_buffer(&display_buffer, 0, bytemuck::cast_slice(&[display]));
+            }
+            KeyCode::F5 => {
+                let path = screenshot::timestamped_path(settings_dir, "snapshot", "snapshot");
+
+                if let Err(err) = save_snapshot(&path, simulated_time) {
+                    eprintln!("failed to save snapshot {}: {err}", path.display());
+                }
+            }
+            KeyCode::F9 => match recorder.take() {
+                Some(recorder) => {
+                    if let Err(err) = recorder.finish() {
+                        eprintln!("failed to finish recording: {err}");
+                    }
+                }
+                None => match Recorder::start(
+                    &settings.recording,
+                    settings_dir,
+                    config.width,
+                    config.height,
+                ) {
+                    Ok(started) => recorder = Some(started),
+                    Err(err) => eprintln!("failed to start recording: {err}"),
+                },
+            },
+            KeyCode::F12 => {
+                let path = screenshot::timestamped_path(settings_dir, "screenshot", "png");
+
+                if let Err(err) = save_screenshot(&path) {
+                    eprintln!("failed to save screenshot {}: {err}", path.display());
+                }
+            }
+            _ => {
+                if let Some(Err(err)) = script
+                    .as_mut()
+                    .map(|script| script.on_key(key_code, &mut species))
+                {
+                    eprintln!("script failed, stopping it: {err}");
+                    script = None;
+                }
+            }
+        },
         Event::WindowEvent {
             window_id,
             event: WindowEvent::RedrawRequested,
         } if window_id == window.id() => {
+            if let Some(control) = &control {
+                let mut state = control::State {
+                    names: &species_map,
+                    species: &mut species,
+                    initial_species: &initial_species,
+                    paused: &mut paused,
+                    dir: settings_dir,
+                    save_screenshot: &save_screenshot,
+                    save_snapshot: &|path| save_snapshot(path, simulated_time),
+                    species_changed: false,
+                    reset: false,
+                };
+                control.poll(|command| state.handle(command));
+                let (species_changed, reset) = (state.species_changed, state.reset);
+
+                if species_changed {
+                    queue.write_buffer(&species_buffer, 0, bytemuck::cast_slice(&species));
+                }
+                if reset {
+                    queue.write_buffer(
+                        &relations_buffer,
+                        0,
+                        bytemuck::cast_slice(&initial_relations),
+                    );
+                    queue.write_buffer(&agents_buffer, 0, bytemuck::cast_slice(&agents));
+                    queue.write_buffer(&weights_buffer, 0, &weights);
+                    queue.write_buffer(
+                        &occupancy_buffer,
+                        0,
+                        bytemuck::cast_slice(&vec![0u32; occupancy_len]),
+                    );
+                    simulated_time = start_time;
+                    step = 0;
+                    hud.reset_steps();
+
+                    if let Some(script) = script.as_mut() {
+                        script.reset(&species, &initial_relations);
+                    }
+                }
+            }
+
             let mut encoder =
                 device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-            let time_delta = start.elapsed().as_secs_f32();
+            let time_delta = match &recorder {
+                Some(recorder) => recorder.time_step,
+                None => start.elapsed().as_secs_f32(),
+            };
             start = Instant::now();
             queue.write_buffer(&time_delta_buffer, 0, bytemuck::cast_slice(&[time_delta]));
 
-            {
-                let mut compute_pass =
-                    encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            if let Some(osc) = osc.as_mut() {
+                if osc.update(time_delta, &mut species) {
+                    queue.write_buffer(&species_buffer, 0, bytemuck::cast_slice(&species));
+                }
+            }
+
+            if !paused {
+                simulated_time += time_delta;
+            }
+            if !timeline.is_empty() {
+                timeline.apply(simulated_time, &mut species);
+                queue.write_buffer(&species_buffer, 0, bytemuck::cast_slice(&species));
+            }
+
+            let frame = Frame {
+                resolution: [width as f32, height as f32],
+                time: started.elapsed().as_secs_f32(),
+                species_count: species.len() as u32,
+            };
+            queue.write_buffer(&frame_buffer, 0, bytemuck::cast_slice(&[frame]));
+
+            if let Some(custom) = shader_watcher.as_mut().and_then(ShaderWatcher::poll) {
+                let pipeline = custom
+                    .and_then(|custom| render_shader::compose(&custom))
+                    .and_then(|source| create_render_pipeline(&source));
+
+                render_pipeline = match pipeline {
+                    Ok(pipeline) => pipeline,
+                    Err(err) => {
+                        eprintln!("custom shader failed, using the built-in one: {err}");
+                        create_render_pipeline(&render_shader::builtin()).unwrap()
+                    }
+                };
+            }
+
+            if render_mode == RenderMode::Trails && !paused {
+                trails.encode(
+                    &mut encoder,
+                    &agents_buffer,
+                    agents.len() as u32,
+                    time_delta,
+                );
+            }
+
+            if !paused {
+                queue.write_buffer(
+                    &lifecycle_buffer,
+                    0,
+                    bytemuck::cast_slice(&vec![0u32; lifecycle_counters]),
+                );
+            }
+
+            for (i, dispatches) in passes.iter().enumerate() {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: gpu_timer.as_ref().map(|timer| timer.writes(i)),
+                });
                 compute_pass.set_bind_group(0, &compute_bind_group, &[]);
 
-                compute_pass.set_pipeline(&main_1_compute_pipeline);
-                compute_pass.dispatch_workgroups(x, y, z);
+                // While paused, only the display pass runs, so render mode changes still show.
+                if paused && i != DISPLAY_PASS {
+                    continue;
+                }
 
-                compute_pass.set_pipeline(&main_2_compute_pipeline);
-                compute_pass.dispatch_workgroups(width, height, 1);
+                for &(pipeline, (x, y, z)) in dispatches {
+                    compute_pass.set_pipeline(pipeline);
+                    compute_pass.dispatch_workgroups(x, y, z);
+                }
+            }
 
-                compute_pass.set_pipeline(&main_3_compute_pipeline);
-                compute_pass.dispatch_workgroups(width, height, 1);
+            if let Some(timer) = &gpu_timer {
+                timer.resolve(&mut encoder);
+            }
+            hud.frame(if paused { 0 } else { 1 });
+
+            if bloom_enabled {
+                bloom.encode(&mut encoder);
             }
 
             let output = surface.get_current_texture().unwrap();
@@ -667,10 +1581,10 @@ async fn run() -> Result<()> {
                 .texture
                 .create_view(&wgpu::TextureViewDescriptor::default());
 
-            {
+            for view in [&view, &capture_view] {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
+                        view,
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Load,
@@ -685,11 +1599,73 @@ async fn run() -> Result<()> {
                 render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
                 render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
                 render_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
+
+                agent_renderer.draw(
+                    &mut render_pass,
+                    &agents_buffer,
+                    agents.len() as u32,
+                    agent_mode,
+                );
+
+                if histograms {
+                    histogram_renderer.draw(&mut render_pass);
+                }
             }
 
             queue.submit(iter::once(encoder.finish()));
+
+            if !paused {
+                step += 1;
+
+                if let Some(Err(err)) = script
+                    .as_mut()
+                    .map(|script| script.on_step(step, simulated_time, &mut species))
+                {
+                    eprintln!("script failed, stopping it: {err}");
+                    script = None;
+                }
+            }
+
+            if settings.hud && hud.due() {
+                let pass_times = gpu_timer
+                    .as_ref()
+                    .and_then(|timer| timer.read(&device).ok());
+
+                let counters = (lifecycle_counters * mem::size_of::<u32>()) as wgpu::BufferAddress;
+                let populations =
+                    readback::read_buffer_range(&device, &queue, &lifecycle_buffer, 0..counters)
+                        .map(|bytes| {
+                            let counts: &[u32] = bytemuck::cast_slice(&bytes);
+                            species_settings
+                                .iter()
+                                .zip(&counts[2..])
+                                .map(|(s, &count)| (s.name.as_str(), count))
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+
+                window.set_title(&hud.title(&populations, pass_times));
+            }
+
+            if let Some(rec) = recorder.as_mut().filter(|_| !paused) {
+                if rec.step() {
+                    let result = read_capture()
+                        .and_then(|pixels| rec.write_frame(&pixels, config.width, config.height));
+
+                    if let Err(err) = result {
+                        eprintln!("failed to record frame, stopping: {err}");
+                        let _ = recorder.take().map(Recorder::finish);
+                    }
+                }
+            }
+
             output.present();
         }
+        Event::LoopExiting => {
+            if let Some(Err(err)) = recorder.take().map(Recorder::finish) {
+                eprintln!("failed to finish recording: {err}");
+            }
+        }
         Event::AboutToWait => {
             window.request_redraw();
         }
@@ -698,3 +1674,202 @@ async fn run() -> Result<()> {
 
     Ok(())
 }
+
+/// Layout of the bindings shared by every pass in `compute.wgsl`.
+fn compute_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::ReadWrite,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 13,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 14,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 15,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 16,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 17,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 18,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+
+/// Order of the steps in `main_1`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Motion {
+    /// Sense, turn, move along the new heading, deposit.
+    #[default]
+    Ordered,
+    /// Move along the heading from before the turn, as older versions did.
+    Legacy,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use wgpu::util::DeviceExt;
+
+    use super::Motion;
+    use crate::{
+        bitmap, compute_bind_group_layout, readback, sensor::SensorWeighting, Agent, Species,
+    };
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 64;
+    const TIME_DELTA: f32 = 0.1;
+
+    /// `hash` from `compute.wgsl`.
+    fn hash(state: u32) -> u32 {
+        let mut hash = state;
+        hash ^= 2747636419;
+        hash = hash.wrapping_mul(2654435769);
+        hash ^= hash >> 16;
+        hash = hash.wrapping_mul(2654435769);
+        hash ^= hash >> 16;
+        hash.wrapping_mul(2654435769)
+    }
+
+    fn species() -> Species {
+        Species {
+            move_speed: 10.0,
+            turn_speed: 2.0,
+            sensor_angle: 0.6,
+            sensor_offset: 5.0,
+            sensor_size: 1,
+            like_index: 0,
+            like_length: 1,
+            hate_index: 1,
+            convert_to: u32::MAX,
+            sensors: 3,
+            steering_temperature: 0.1,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    fn agent() -> Agent {
+        Agent {
+            position: [20.5, 30.5],
+            angle: 0.3,
+            move_speed_scale: 1.0,
+            turn_speed_scale: 1.0,
+            sensor_angle_scale: 1.0,
+            sensor_offset_scale: 1.0,
+            energy: 1.0,
+            alive: 1,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    /// Pheromone increasing along y, so an agent heading right turns left.
+    fn weights() -> Vec<f32> {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |_| y as f32 / HEIGHT as f32))
+            .collect()
+    }
+
+    /// One classic step of a lone agent of a species liking only itself over a square footprint,
+    /// on the CPU.
+    fn reference(agent: Agent, spec: &Species, weights: &[f32], motion: Motion) -> (Agent, usize) {
+        let sense = |dir: f32| {
+            let angle = agent.angle + spec.sensor_angle * agent.sensor_angle_scale * dir;
+            let offset = spec.sensor_offset * agent.sensor_offset_scale;
+            let x = agent.position[0] + angle.cos() * offset;
+            let y = agent.position[1] + angle.sin() * offset;
+
+            let size = spec.sensor_size as i32;
+            let mut sum = 0.0;
+            for offset_x in -size..=size {
+                for offset_y in -size..=size {
+                    let pos_x = (x + offset_x as f32) as u32;
+                    let pos_y = (y + offset_y as f32) as u32;
+                    let distance = ((offset_x * offset_x + offset_y * offset_y) as f32).sqrt();
+                    let factor = if spec.sensor_weighting == SensorWeighting::Linear as u32 {
+                        (1.0 - distance / (size + 1) as f32).max(0.0)
+                    } else {
+                        1.0
+                    };
+
+                    if pos_x < WIDTH && pos_y < HEIGHT {
+                        sum += weights[(pos_x + pos_y * WIDTH) as usize] * factor;
+                    }
+                }
+            }
+            sum
+        };
+
+        let (forward, left, right) = (sense(0.0), sense(1.0), sense(-1.0));
+
+        let [x, y] = agent.position;
+        let random = hash(
+            (y as u32 * WIDTH)
+                .wrapping_add(x as u32)
+                .wrapping_add(hash(0)),
+        ) as f32
+            / 4294967295.0;
+        // The shader's approximation of pi, so both round the same.
+        #[allow(clippy::approx_constant)]
+        let turn_speed = spec.turn_speed * agent.turn_speed_scale * 2.0 * 3.1415;
+
+        let turn = if forward > left && forward > right {
+            0.0
+        } else if forward < left && forward < right {
+            (random - 0.5) * 2.0 * turn_speed * TIME_DELTA
+        } else if right > left {
+            -random * turn_speed * TIME_DELTA
+        } else if left > right {
+            random * turn_speed * TIME_DELTA
+        } else {
+            0.0
+        };
+
+        let angle = agent.angle + turn;
+        let heading = match motion {
+            Motion::Ordered => angle,
+            Motion::Legacy => agent.angle,
+        };
+
+        let move_speed = spec.move_speed * agent.move_speed_scale;
+        let position = [
+            x + heading.cos() * move_speed * TIME_DELTA,
+            y + heading.sin() * move_speed * TIME_DELTA,
+        ];
+        let deposit = (position[0] as u32 + position[1] as u32 * WIDTH) as usize;
+
+        (
+            Agent {
+                position,
+                angle,
+                ..agent
+            },
+            deposit,
+        )
+    }
+
+    /// Runs `main_1` once for a lone agent and returns it with the weights afterwards.
+    fn gpu(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        agent: Agent,
+        spec: &Species,
+        weights: &[f32],
+        motion: Motion,
+    ) -> (Agent, Vec<f32>) {
+        let uniform = |contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+        let storage = |contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let texture = |format: wgpu::TextureFormat, usage: wgpu::TextureUsages| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: WIDTH,
+                        height: HEIGHT,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+
+        let lifecycle_len = 2 + 1 + crate::histogram::PARAMETERS.len() * crate::histogram::BINS + 2;
+
+        let buffers = [
+            uniform(bytemuck::cast_slice(&[WIDTH])),
+            uniform(bytemuck::cast_slice(&[HEIGHT])),
+            uniform(bytemuck::cast_slice(&[1u32])),
+            uniform(bytemuck::cast_slice(&[1u32])),
+            storage(bytemuck::cast_slice(&[*spec])),
+            storage(bytemuck::cast_slice(&[0u32])),
+            storage(bytemuck::cast_slice(&[agent])),
+            storage(bytemuck::cast_slice(weights)),
+            storage(bytemuck::cast_slice(&[TIME_DELTA])),
+            uniform(&[0; mem::size_of::<crate::display::Display>()]),
+            storage(bytemuck::cast_slice(&vec![0u32; lifecycle_len])),
+            storage(bytemuck::cast_slice(&[0.0f32])),
+            uniform(&[0; mem::size_of::<crate::flow::Flow>()]),
+            uniform(bytemuck::cast_slice(&[motion as u32])),
+            storage(bytemuck::cast_slice(&[0u32])),
+        ];
+        let field = texture(
+            wgpu::TextureFormat::Rgba32Float,
+            wgpu::TextureUsages::STORAGE_BINDING,
+        );
+        let trails = texture(
+            wgpu::TextureFormat::Rgba16Float,
+            wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let flow = bitmap::texture(device, queue, &[[0.0; 2]], 1, 1);
+        let environment = bitmap::texture(device, queue, &[[1.0; 2]], 1, 1);
+
+        let layout = compute_bind_group_layout(device);
+        let views = [(4, &field), (11, &trails), (13, &flow), (16, &environment)];
+        let buffer_bindings = [0, 1, 2, 3, 5, 6, 7, 8, 9, 10, 12, 14, 15, 17, 18];
+
+        let entries = buffer_bindings
+            .iter()
+            .zip(&buffers)
+            .map(|(&binding, buffer)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .chain(views.iter().map(|&(binding, view)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            }))
+            .collect::<Vec<_>>();
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &entries,
+        });
+
+        let shader = device.create_shader_module(include_species_wgsl!("compute.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main_1",
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let agent = readback::read_buffer(device, queue, &buffers[6]).unwrap();
+        let weights = readback::read_buffer(device, queue, &buffers[7]).unwrap();
+
+        (
+            bytemuck::pod_read_unaligned(&agent),
+            bytemuck::cast_slice(&weights).to_vec(),
+        )
+    }
+
+    #[test]
+    fn main_1_matches_cpu_reference() {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()));
+        let Some(adapter) = adapter else {
+            eprintln!("no adapter, skipping");
+            return;
+        };
+
+        let features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        if !adapter.features().contains(features) {
+            eprintln!("adapter lacks read-write storage textures, skipping");
+            return;
+        }
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: features,
+                ..Default::default()
+            },
+            None,
+        ))
+        .unwrap();
+
+        let spec = species();
+        let weights = weights();
+        let mut positions = Vec::new();
+
+        for motion in [Motion::Ordered, Motion::Legacy] {
+            let (expected, deposit) = reference(agent(), &spec, &weights, motion);
+            let (actual, actual_weights) = gpu(&device, &queue, agent(), &spec, &weights, motion);
+
+            assert!((actual.angle - expected.angle).abs() < 1e-4);
+            assert!((actual.position[0] - expected.position[0]).abs() < 1e-3);
+            assert!((actual.position[1] - expected.position[1]).abs() < 1e-3);
+            assert_eq!(actual_weights[deposit], 1.0);
+
+            positions.push(actual.position);
+        }
+
+        assert_ne!(positions[0], positions[1]);
+
+        // Pheromone only under the corner of the left sensor's footprint, which lies further than
+        // one pixel beyond `sensor_size` and so must not count at all, leaving the agent straight.
+        let spec = Species {
+            sensor_size: 3,
+            sensor_weighting: SensorWeighting::Linear as u32,
+            ..species()
+        };
+        let mut weights = vec![0.0; (WIDTH * HEIGHT) as usize];
+        weights[(26 + 37 * WIDTH) as usize] = 1.0;
+
+        let (expected, _) = reference(agent(), &spec, &weights, Motion::Ordered);
+        let (actual, _) = gpu(&device, &queue, agent(), &spec, &weights, Motion::Ordered);
+        assert_eq!(expected.angle, agent().angle);
+        assert!((actual.angle - expected.angle).abs() < 1e-4);
+    }
+}
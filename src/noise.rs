@@ -0,0 +1,37 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Smoothly interpolated random values on a square lattice, in -1..1.
+pub struct ValueNoise {
+    lattice: Vec<f32>,
+    columns: usize,
+    scale: f32,
+}
+
+impl ValueNoise {
+    /// Covers `width`x`height` pixels, plus a margin of one cell, with cells of `scale` pixels.
+    pub fn new(width: u32, height: u32, scale: f32, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let columns = (width as f32 / scale).ceil() as usize + 2;
+        let rows = (height as f32 / scale).ceil() as usize + 2;
+
+        Self {
+            lattice: (0..columns * rows)
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect(),
+            columns,
+            scale,
+        }
+    }
+
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = (x / self.scale, y / self.scale);
+        let (cell_x, cell_y) = (x.floor() as usize, y.floor() as usize);
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x.fract()), smooth(y.fract()));
+
+        let value = |i: usize, j: usize| self.lattice[(cell_y + j) * self.columns + cell_x + i];
+        let top = value(0, 0) + (value(1, 0) - value(0, 0)) * tx;
+        let bottom = value(0, 1) + (value(1, 1) - value(0, 1)) * tx;
+        top + (bottom - top) * ty
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    net::UdpSocket,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+
+use crate::{timeline::Field, Species};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscSettings {
+    pub enabled: bool,
+    /// Address to listen on, `0.0.0.0` accepts controllers on other devices.
+    pub host: String,
+    pub port: u16,
+    /// Seconds a field takes to move about two thirds of the way to a new value, zero jumps.
+    pub smoothing: f32,
+    /// Fields whose incoming 0..1 values are mapped to a `[low, high]` range, others are used as is.
+    pub ranges: HashMap<Field, [f32; 2]>,
+}
+
+impl Default for OscSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: 9000,
+            smoothing: 0.1,
+            ranges: HashMap::new(),
+        }
+    }
+}
+
+/// Receives Open Sound Control messages addressed to `/species/<name>/<field>` over UDP.
+pub struct Osc {
+    socket: UdpSocket,
+    port: u16,
+    smoothing: f32,
+    ranges: HashMap<Field, [f32; 2]>,
+    names: HashMap<String, usize>,
+    /// Values fields are moving toward, removed once reached.
+    targets: HashMap<(usize, Field), f32>,
+    /// Addresses already reported as unknown.
+    ignored: HashSet<String>,
+}
+
+impl Osc {
+    pub fn start(settings: &OscSettings, names: &HashMap<String, usize>) -> Result<Self> {
+        let socket = UdpSocket::bind((settings.host.as_str(), settings.port)).map_err(|err| {
+            anyhow!(
+                "failed to listen for OSC on {}:{}: {err}",
+                settings.host,
+                settings.port
+            )
+        })?;
+        socket.set_nonblocking(true)?;
+        let port = socket.local_addr()?.port();
+
+        Ok(Self {
+            socket,
+            port,
+            smoothing: settings.smoothing,
+            ranges: settings.ranges.clone(),
+            names: names.clone(),
+            targets: HashMap::new(),
+            ignored: HashSet::new(),
+        })
+    }
+
+    /// The port listened on, which `start` picks if the settings' port is zero.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Reads the messages received since the last update and moves the fields toward them,
+    /// returning whether any species changed.
+    pub fn update(&mut self, time_delta: f32, species: &mut [Species]) -> bool {
+        let mut buffer = [0; 65536];
+        let mut messages = Vec::new();
+
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, _)) => {
+                    if parse(&buffer[..length], &mut messages).is_none() {
+                        eprintln!("ignoring malformed OSC packet");
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    eprintln!("failed to receive OSC: {err}");
+                    break;
+                }
+            }
+        }
+
+        for (address, value) in messages {
+            match self.target(&address) {
+                Some((index, field)) => {
+                    let value = match self.ranges.get(&field) {
+                        Some([low, high]) => low + (high - low) * value,
+                        None => value,
+                    };
+                    self.targets.insert((index, field), value);
+                }
+                None => {
+                    if self.ignored.insert(address.clone()) {
+                        eprintln!("ignoring OSC messages to {address}");
+                    }
+                }
+            }
+        }
+
+        let blend = if self.smoothing > 0.0 {
+            1.0 - (-time_delta / self.smoothing).exp()
+        } else {
+            1.0
+        };
+
+        let changed = !self.targets.is_empty();
+        self.targets.retain(|&(index, field), &mut target| {
+            let value = field.get(&mut species[index]);
+            *value += (target - *value) * blend;
+
+            if (target - *value).abs() <= f32::EPSILON * target.abs().max(1.0) {
+                *value = target;
+                return false;
+            }
+            true
+        });
+
+        changed
+    }
+
+    fn target(&self, address: &str) -> Option<(usize, Field)> {
+        let mut parts = address.strip_prefix("/species/")?.split('/');
+        let (name, field, None) = (parts.next()?, parts.next()?, parts.next()) else {
+            return None;
+        };
+
+        let field = Field::deserialize(field.into_deserializer())
+            .map_err(|_: serde::de::value::Error| ())
+            .ok()?;
+        Some((*self.names.get(name)?, field))
+    }
+}
+
+/// Appends the address and first finite numeric argument of every message in a packet, which may
+/// be a bundle. Returns `None` if the packet is malformed.
+fn parse(packet: &[u8], messages: &mut Vec<(String, f32)>) -> Option<()> {
+    if let Some(mut elements) = packet.strip_prefix(b"#bundle\0") {
+        // Skip the time tag, every message is applied as soon as it arrives.
+        elements = elements.get(8..)?;
+
+        while !elements.is_empty() {
+            let size = u32::from_be_bytes(elements.get(..4)?.try_into().ok()?) as usize;
+            parse(elements.get(4..4 + size)?, messages)?;
+            elements = &elements[4 + size..];
+        }
+
+        return Some(());
+    }
+
+    let (address, rest) = string(packet)?;
+    let (tags, arguments) = string(rest)?;
+
+    let value = match tags.strip_prefix(',')?.chars().next() {
+        Some('f') => f32::from_be_bytes(arguments.get(..4)?.try_into().ok()?),
+        Some('i') => i32::from_be_bytes(arguments.get(..4)?.try_into().ok()?) as f32,
+        Some('d') => f64::from_be_bytes(arguments.get(..8)?.try_into().ok()?) as f32,
+        // Messages without a numeric first argument carry nothing to map.
+        _ => return Some(()),
+    };
+
+    // A NaN or infinite target would never be reached, or poison the field it moves.
+    if value.is_finite() {
+        messages.push((address.to_string(), value));
+    }
+
+    Some(())
+}
+
+/// Reads a null terminated string padded to four bytes, returning it and the bytes after it.
+fn string(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let end = bytes.iter().position(|&byte| byte == 0)?;
+    let padded = (end + 4) & !3;
+
+    Some((
+        std::str::from_utf8(&bytes[..end]).ok()?,
+        bytes.get(padded..)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    fn message(address: &str, value: f32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for string in [address, ",f"] {
+            bytes.extend(string.as_bytes());
+            bytes.resize((bytes.len() + 4) & !3, 0);
+        }
+        bytes.extend(value.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn parse_drops_non_finite_values() {
+        let mut messages = Vec::new();
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 2.0] {
+            parse(&message("/species/white/move_speed", value), &mut messages).unwrap();
+        }
+
+        let mut double = b"/a\0\0,d\0\0".to_vec();
+        double.extend(f64::MAX.to_be_bytes());
+        parse(&double, &mut messages).unwrap();
+
+        assert_eq!(messages, [("/species/white/move_speed".to_string(), 2.0)]);
+    }
+
+    #[test]
+    fn messages_set_species_fields() {
+        let settings: OscSettings =
+            toml::from_str("port = 0\nsmoothing = 1.0\n[ranges]\nsensor_angle = [0.0, 2.0]")
+                .unwrap();
+        let names = HashMap::from([("white".to_string(), 0)]);
+        let mut osc = Osc::start(&settings, &names).unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send = |packet: &[u8]| {
+            sender.send_to(packet, ("127.0.0.1", osc.port())).unwrap();
+        };
+
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        for packet in [
+            message("/species/white/move_speed", 50.0),
+            message("/species/white/sensor_angle", 0.25),
+        ] {
+            bundle.extend((packet.len() as u32).to_be_bytes());
+            bundle.extend(packet);
+        }
+        send(&bundle);
+        send(&message("/species/purple/move_speed", 1.0));
+        send(&message("/species/white/turn_speed", f32::NAN));
+        send(&message("/species/white/decay_rate", f32::INFINITY));
+        send(b"garbage");
+        thread::sleep(Duration::from_millis(50));
+
+        let mut species = [bytemuck::Zeroable::zeroed()];
+        assert!(osc.update(1.0, &mut species));
+
+        // One smoothing time covers 1 - 1/e of the way, and the range maps 0.25 to 0.5.
+        let blend = 1.0 - (-1.0f32).exp();
+        assert!((species[0].move_speed - 50.0 * blend).abs() < 1e-4);
+        assert!((species[0].sensor_angle - 0.5 * blend).abs() < 1e-4);
+        assert_eq!(species[0].turn_speed, 0.0);
+        assert_eq!(species[0].decay_rate, 0.0);
+
+        for _ in 0..100 {
+            osc.update(1.0, &mut species);
+        }
+        assert_eq!(species[0].move_speed, 50.0);
+        assert!(!osc.update(1.0, &mut species));
+    }
+}
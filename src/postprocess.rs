@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{include_wgsl, util::DeviceExt, StoreOp};
+
+const BLOOM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Curve mapping the HDR field to the displayable range after exposure.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tonemap {
+    /// Clamp, as the surface would.
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl Tonemap {
+    pub fn next(self) -> Self {
+        match self {
+            Tonemap::None => Tonemap::Reinhard,
+            Tonemap::Reinhard => Tonemap::Aces,
+            Tonemap::Aces => Tonemap::None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessSettings {
+    pub bloom: bool,
+    /// Brightness above which pixels contribute to the bloom.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Number of half resolution blur levels.
+    pub bloom_levels: u32,
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            bloom: false,
+            bloom_threshold: 0.6,
+            bloom_intensity: 0.8,
+            bloom_levels: 5,
+            exposure: 1.0,
+            tonemap: Tonemap::None,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Post {
+    pub exposure: f32,
+    pub bloom_intensity: f32,
+    pub tonemap: u32,
+    pub bloom: u32,
+}
+
+impl Post {
+    pub fn new(settings: &PostProcessSettings, bloom: bool, tonemap: Tonemap) -> Self {
+        Self {
+            exposure: settings.exposure,
+            bloom_intensity: settings.bloom_intensity,
+            tonemap: tonemap as u32,
+            bloom: bloom as u32,
+        }
+    }
+}
+
+/// Bright pass followed by a dual filter blur pyramid, leaving the bloom in the first level.
+pub struct Bloom {
+    views: Vec<wgpu::TextureView>,
+    bright_pipeline: wgpu::RenderPipeline,
+    down_pipeline: wgpu::RenderPipeline,
+    up_pipeline: wgpu::RenderPipeline,
+    bright_bind_group: wgpu::BindGroup,
+    down_bind_groups: Vec<wgpu::BindGroup>,
+    up_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl Bloom {
+    pub fn new(
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+        settings: &PostProcessSettings,
+    ) -> Self {
+        let levels = settings.bloom_levels.max(1);
+
+        let views = (1..=levels)
+            .map(|level| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: (width >> level).max(1),
+                        height: (height >> level).max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: BLOOM_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                });
+
+                texture.create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect::<Vec<_>>();
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let threshold_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[settings.bloom_threshold]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = |view: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: threshold_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        let bright_bind_group = bind_group(source);
+        let down_bind_groups = views[..views.len() - 1].iter().map(bind_group).collect();
+        let up_bind_groups = views[1..].iter().map(bind_group).collect();
+
+        let shader = device.create_shader_module(include_wgsl!("postprocess.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |entry_point: &str, blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: BLOOM_FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+
+        Self {
+            bright_pipeline: pipeline("fs_bright", wgpu::BlendState::REPLACE),
+            down_pipeline: pipeline("fs_down", wgpu::BlendState::REPLACE),
+            up_pipeline: pipeline("fs_up", additive),
+            views,
+            bright_bind_group,
+            down_bind_groups,
+            up_bind_groups,
+        }
+    }
+
+    /// The blurred bright parts of the source at half resolution, valid after `encode`.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.views[0]
+    }
+
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder) {
+        pass(
+            encoder,
+            &self.views[0],
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.bright_pipeline,
+            &self.bright_bind_group,
+        );
+
+        for (i, bind_group) in self.down_bind_groups.iter().enumerate() {
+            pass(
+                encoder,
+                &self.views[i + 1],
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                &self.down_pipeline,
+                bind_group,
+            );
+        }
+
+        for (i, bind_group) in self.up_bind_groups.iter().enumerate().rev() {
+            pass(
+                encoder,
+                &self.views[i],
+                wgpu::LoadOp::Load,
+                &self.up_pipeline,
+                bind_group,
+            );
+        }
+    }
+}
+
+fn pass(
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
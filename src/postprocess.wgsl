@@ -0,0 +1,66 @@
+@group(0)
+@binding(0)
+var source: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var s: sampler;
+
+@group(0)
+@binding(2)
+var<uniform> threshold: f32;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// Fullscreen triangle, no vertex buffer needed.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.tex_coords = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, s, in.tex_coords).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Dual filter downsample: the center and four diagonal bilinear taps.
+@fragment
+fn fs_down(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+
+    var sum = textureSample(source, s, in.tex_coords).rgb * 4.0;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(-texel.x, -texel.y)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(texel.x, -texel.y)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(-texel.x, texel.y)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(texel.x, texel.y)).rgb;
+
+    return vec4<f32>(sum / 8.0, 1.0);
+}
+
+// Dual filter upsample: a tent of eight taps, added onto the next larger level.
+@fragment
+fn fs_up(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+
+    var sum = textureSample(source, s, in.tex_coords + vec2<f32>(-2.0 * texel.x, 0.0)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(2.0 * texel.x, 0.0)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(0.0, -2.0 * texel.y)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(0.0, 2.0 * texel.y)).rgb;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(-texel.x, -texel.y)).rgb * 2.0;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(texel.x, -texel.y)).rgb * 2.0;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(-texel.x, texel.y)).rgb * 2.0;
+    sum += textureSample(source, s, in.tex_coords + vec2<f32>(texel.x, texel.y)).rgb * 2.0;
+
+    return vec4<f32>(sum / 12.0, 1.0);
+}
@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+
+/// Copies a texture with a single plane format into a mapped staging buffer and returns its texels
+/// row by row, without padding.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>> {
+    let (width, height) = (texture.width(), texture.height());
+    let texel_size = texture
+        .format()
+        .block_copy_size(None)
+        .ok_or_else(|| anyhow!("cannot read back {:?} textures", texture.format()))?;
+    let unpadded_bytes_per_row = width * texel_size;
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(alignment) * alignment;
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: padded_bytes_per_row as wgpu::BufferAddress * height as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &staging_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let bytes = map(device, &staging_buffer)?;
+
+    let mut texels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in bytes.chunks_exact(padded_bytes_per_row as usize) {
+        texels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+
+    Ok(texels)
+}
+
+/// Copies a buffer created with `COPY_SRC` into a mapped staging buffer and returns its bytes.
+pub fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<u8>> {
+    read_buffer_range(device, queue, buffer, 0..buffer.size())
+}
+
+/// Like `read_buffer`, but only reads `range`, which has to be a multiple of 4 bytes.
+pub fn read_buffer_range(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    range: Range<wgpu::BufferAddress>,
+) -> Result<Vec<u8>> {
+    let size = range.end - range.start;
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, range.start, &staging_buffer, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    map(device, &staging_buffer)
+}
+
+fn map(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Result<Vec<u8>> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let bytes = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+
+    Ok(bytes)
+}
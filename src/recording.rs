@@ -0,0 +1,156 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::screenshot;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    /// Capture every nth simulated step.
+    pub every: u32,
+    /// Simulated seconds per step while recording, independent of the real frame rate.
+    pub time_step: f32,
+    /// Directory for the numbered frames or the video, relative to the settings file.
+    pub output: String,
+    /// Pipe frames into `ffmpeg` instead of writing PNGs when it can be spawned.
+    pub ffmpeg: bool,
+    /// Frame rate of the encoded video.
+    pub fps: u32,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            every: 1,
+            time_step: 1.0 / 60.0,
+            output: "recordings".to_string(),
+            ffmpeg: true,
+            fps: 60,
+        }
+    }
+}
+
+enum Sink {
+    Png { dir: PathBuf },
+    Ffmpeg { child: Child },
+}
+
+pub struct Recorder {
+    sink: Sink,
+    every: u32,
+    step: u64,
+    frame: u64,
+    pub time_step: f32,
+}
+
+impl Recorder {
+    pub fn start(
+        settings: &RecordingSettings,
+        dir: &Path,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let output = screenshot::timestamped_path(&dir.join(&settings.output), "recording", "mp4");
+        fs::create_dir_all(output.parent().unwrap())?;
+
+        let ffmpeg = settings
+            .ffmpeg
+            .then(|| {
+                Command::new("ffmpeg")
+                    .args([
+                        "-y",
+                        "-loglevel",
+                        "error",
+                        "-f",
+                        "rawvideo",
+                        "-pix_fmt",
+                        "rgba",
+                    ])
+                    .args(["-s", &format!("{width}x{height}")])
+                    .args(["-r", &settings.fps.to_string()])
+                    .args(["-i", "-"])
+                    // yuv420p needs even sizes, so odd ones get a black row or column.
+                    .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+                    .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(&output)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .ok()
+            })
+            .flatten();
+
+        let sink = match ffmpeg {
+            Some(child) => Sink::Ffmpeg { child },
+            None => {
+                let dir = output.with_extension("");
+                fs::create_dir_all(&dir)?;
+                Sink::Png { dir }
+            }
+        };
+
+        Ok(Self {
+            sink,
+            every: settings.every.max(1),
+            step: 0,
+            frame: 0,
+            time_step: settings.time_step,
+        })
+    }
+
+    /// Advances the step counter and returns whether this step should be captured.
+    pub fn step(&mut self) -> bool {
+        let capture = self.step.is_multiple_of(self.every as u64);
+        self.step += 1;
+        capture
+    }
+
+    pub fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+        match &mut self.sink {
+            Sink::Png { dir } => {
+                let path = dir.join(format!("{:06}.png", self.frame));
+                screenshot::write_png(&path, pixels, width, height, None)?;
+            }
+            Sink::Ffmpeg { child } => {
+                // ffmpeg rejects unusable input only after it started, so check it is still running.
+                if let Some(status) = child.try_wait()? {
+                    return Err(anyhow!("ffmpeg exited with {status}"));
+                }
+
+                let stdin = child
+                    .stdin
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("ffmpeg stdin closed"))?;
+                match stdin.write_all(pixels) {
+                    Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+                        return Err(anyhow!("ffmpeg exited with {}", child.wait()?));
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if let Sink::Ffmpeg { mut child } = self.sink {
+            drop(child.stdin.take());
+            let status = child.wait()?;
+
+            if !status.success() {
+                return Err(anyhow!("ffmpeg exited with {status}"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,30 +1,5 @@
-@group(0)
-@binding(0)
-var texture: texture_2d<f32>;
-
-@group(0)
-@binding(1)
-var s: sampler;
-
-struct VertexInput {
-    @location(0) position: vec2<f32>,
-    @location(1) tex_coords: vec2<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-}
-
-@vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
-    var out: VertexOutput;
-    out.tex_coords = in.tex_coords;
-    out.position = vec4<f32>(in.position, 1.0, 1.0);
-    return out;
-}
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, s, in.tex_coords);
+    let color = textureSample(texture, s, in.tex_coords).rgb;
+    return vec4<f32>(post_process(color, in.tex_coords), 1.0);
 }
@@ -0,0 +1,109 @@
+// Shared by the built-in and custom render shaders, which only provide `fs_main`.
+
+struct Post {
+    exposure: f32,
+    bloom_intensity: f32,
+    tonemap: u32,
+    bloom: u32,
+}
+
+struct Frame {
+    resolution: vec2<f32>,
+    time: f32,
+    species_count: u32,
+}
+
+// The composited field written by `main_2`.
+@group(0)
+@binding(0)
+var texture: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var s: sampler;
+
+@group(0)
+@binding(2)
+var bloom_texture: texture_2d<f32>;
+
+@group(0)
+@binding(3)
+var<uniform> post: Post;
+
+// Per-species pheromone planes, read them with `weight`.
+@group(0)
+@binding(4)
+var<storage> weights: array<f32>;
+
+@group(0)
+@binding(5)
+var<storage> species: array<Species>;
+
+@group(0)
+@binding(6)
+var<uniform> frame: Frame;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.position = vec4<f32>(in.position, 1.0, 1.0);
+    return out;
+}
+
+fn pixel(tex_coords: vec2<f32>) -> vec2<u32> {
+    let resolution = vec2<u32>(frame.resolution);
+    return min(vec2<u32>(tex_coords * frame.resolution), resolution - vec2<u32>(1u));
+}
+
+// Concentration of a species at a pixel.
+fn weight(species_index: u32, pixel: vec2<u32>) -> f32 {
+    let width = u32(frame.resolution.x);
+    return weights[(pixel.x + pixel.y * width) * frame.species_count + species_index];
+}
+
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Adds bloom, applies exposure and tonemapping as configured in `[postprocess]`.
+fn post_process(color: vec3<f32>, tex_coords: vec2<f32>) -> vec3<f32> {
+    var result = color;
+
+    if (post.bloom != 0u) {
+        result += textureSample(bloom_texture, s, tex_coords).rgb * post.bloom_intensity;
+    }
+
+    result *= post.exposure;
+
+    switch post.tonemap {
+        case TONEMAP_REINHARD {
+            result = result / (result + vec3<f32>(1.0));
+        }
+        case TONEMAP_ACES {
+            result = aces(result);
+        }
+        default {}
+    }
+
+    return result;
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+const SPECIES: &str = include_str!("species.wgsl");
+const PRELUDE: &str = include_str!("render_prelude.wgsl");
+const BUILTIN: &str = include_str!("render.wgsl");
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Frame {
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub species_count: u32,
+}
+
+/// The built-in render shader.
+pub fn builtin() -> String {
+    format!("{SPECIES}\n{PRELUDE}\n{BUILTIN}")
+}
+
+/// Prepends the species struct and prelude to a custom `fs_main` and validates the result with naga.
+pub fn compose(custom: &str) -> Result<String> {
+    let source = format!("{SPECIES}\n{PRELUDE}\n{custom}");
+
+    let module = naga::front::wgsl::parse_str(&source)
+        .map_err(|err| anyhow!(err.emit_to_string(&source)))?;
+
+    let has_fs_main = module
+        .entry_points
+        .iter()
+        .any(|entry| entry.name == "fs_main" && entry.stage == naga::ShaderStage::Fragment);
+    if !has_fs_main {
+        bail!("custom shader has no @fragment fn fs_main");
+    }
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|err| anyhow!(err.emit_to_string(&source)))?;
+
+    Ok(source)
+}
+
+/// Watches a custom shader file and reports its contents whenever it changes.
+pub struct ShaderWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            modified: None,
+            checked: Instant::now() - POLL_INTERVAL,
+        }
+    }
+
+    /// Returns the file's contents if it was modified since the last call.
+    pub fn poll(&mut self) -> Option<Result<String>> {
+        if self.checked.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.checked = Instant::now();
+
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        Some(
+            fs::read_to_string(&self.path)
+                .map_err(|err| anyhow!("failed to read {}: {err}", self.path.display())),
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+
+/// Converts texels read back from a render target to opaque 8-bit RGBA. The color bytes are
+/// already what the surface presents, only BGRA formats need their channels reordered.
+pub fn to_rgba8(texels: &[u8], format: wgpu::TextureFormat) -> Result<Vec<u8>> {
+    let [r, g, b] = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => [0, 1, 2],
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => [2, 1, 0],
+        _ => bail!("cannot capture {format:?} surfaces"),
+    };
+
+    Ok(texels
+        .chunks_exact(4)
+        .flat_map(|texel| [texel[r], texel[g], texel[b], 255])
+        .collect())
+}
+
+/// Writes 8-bit RGBA pixels as a PNG, optionally embedding the settings as a `Settings` text chunk.
+pub fn write_png(
+    path: &Path,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    settings: Option<&str>,
+) -> Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    if let Some(settings) = settings {
+        encoder.add_itxt_chunk("Settings".to_string(), settings.to_string())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Returns `<dir>/<prefix>_<unix millis>.<extension>`.
+pub fn timestamped_path(dir: &Path, prefix: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    dir.join(format!("{prefix}_{millis}.{extension}"))
+}
@@ -0,0 +1,695 @@
+use std::{
+    cell::RefCell, collections::HashMap, f32::consts::PI, fs, mem, path::Path, rc::Rc, sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Scope, AST};
+use serde::{de::IntoDeserializer, Deserialize};
+use winit::keyboard::KeyCode;
+
+use crate::{readback, timeline::Field, Agent, Species};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Buffers a script reads from and writes to.
+pub struct Gpu {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub species: Arc<wgpu::Buffer>,
+    pub relations: Arc<wgpu::Buffer>,
+    pub agents: Arc<wgpu::Buffer>,
+    pub weights: Arc<wgpu::Buffer>,
+    pub lifecycle: Arc<wgpu::Buffer>,
+}
+
+#[derive(Clone, Copy)]
+enum Relation {
+    Like,
+    Hate,
+    Ignore,
+}
+
+/// State shared by the functions registered on the engine.
+struct Host {
+    gpu: Gpu,
+    width: u32,
+    height: u32,
+    names: HashMap<String, usize>,
+    species: Vec<Species>,
+    likes: Vec<Vec<u32>>,
+    hates: Vec<Vec<u32>>,
+    /// Whether `species` changed during the current call.
+    changed: bool,
+    rng: StdRng,
+}
+
+impl Host {
+    fn species_index(&self, name: &str) -> ScriptResult<usize> {
+        self.names
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("unknown species {name}").into())
+    }
+
+    fn field(name: &str) -> ScriptResult<Field> {
+        Field::deserialize(name.into_deserializer())
+            .map_err(|_: serde::de::value::Error| format!("unknown field {name}").into())
+    }
+
+    /// Replaces the species and the relations they index into.
+    fn restore(&mut self, species: &[Species], relations: &[u32]) {
+        let slice =
+            |index: u32, length: u32| relations[index as usize..(index + length) as usize].to_vec();
+        self.species = species.to_vec();
+        self.likes = species
+            .iter()
+            .map(|s| slice(s.like_index, s.like_length))
+            .collect();
+        self.hates = species
+            .iter()
+            .map(|s| slice(s.hate_index, s.hate_length))
+            .collect();
+    }
+
+    fn write_species(&mut self) {
+        self.changed = true;
+        self.gpu
+            .queue
+            .write_buffer(&self.gpu.species, 0, bytemuck::cast_slice(&self.species));
+    }
+
+    fn relate(&mut self, species: usize, other: usize, relation: Relation) {
+        let other = other as u32;
+        self.likes[species].retain(|&s| s != other);
+        self.hates[species].retain(|&s| s != other);
+
+        match relation {
+            Relation::Like => self.likes[species].push(other),
+            Relation::Hate => self.hates[species].push(other),
+            Relation::Ignore => {}
+        }
+
+        let mut relations: Vec<u32> = Vec::new();
+        for (i, spec) in self.species.iter_mut().enumerate() {
+            spec.like_index = relations.len() as u32;
+            spec.like_length = self.likes[i].len() as u32;
+            relations.extend(&self.likes[i]);
+
+            spec.hate_index = relations.len() as u32;
+            spec.hate_length = self.hates[i].len() as u32;
+            relations.extend(&self.hates[i]);
+        }
+
+        self.gpu
+            .queue
+            .write_buffer(&self.gpu.relations, 0, bytemuck::cast_slice(&relations));
+        self.write_species();
+    }
+
+    /// Byte range of the pheromone of every species from pixel `start` up to, but excluding, `end`.
+    fn pixels(&self, start: u32, end: u32) -> std::ops::Range<wgpu::BufferAddress> {
+        let size = (self.species.len() * mem::size_of::<f32>()) as wgpu::BufferAddress;
+        start as wgpu::BufferAddress * size..end as wgpu::BufferAddress * size
+    }
+
+    fn concentration(&self, species: usize, x: f64, y: f64) -> ScriptResult<f64> {
+        let (x, y) = (x as i64, y as i64);
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return Ok(0.0);
+        }
+
+        let pixel = x as u32 + y as u32 * self.width;
+        let bytes = readback::read_buffer_range(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.weights,
+            self.pixels(pixel, pixel + 1),
+        )
+        .map_err(|err| err.to_string())?;
+
+        let weights: Vec<f32> = unaligned(&bytes);
+        Ok(weights[species] as f64)
+    }
+
+    fn population(&self, species: usize) -> ScriptResult<i64> {
+        let size = mem::size_of::<u32>() as wgpu::BufferAddress;
+        let counter = (2 + species) as wgpu::BufferAddress;
+        let bytes = readback::read_buffer_range(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.lifecycle,
+            counter * size..(counter + 1) * size,
+        )
+        .map_err(|err| err.to_string())?;
+
+        Ok(bytemuck::pod_read_unaligned::<u32>(&bytes) as i64)
+    }
+
+    /// Adds `amount` of pheromone to every pixel within `radius`, never going below zero.
+    fn deposit(
+        &self,
+        species: usize,
+        x: f64,
+        y: f64,
+        radius: f64,
+        amount: f64,
+    ) -> ScriptResult<()> {
+        let top = (y - radius).floor().max(0.0) as u32;
+        let bottom = ((y + radius).ceil() as u32 + 1).min(self.height);
+        if top >= bottom {
+            return Ok(());
+        }
+
+        let range = self.pixels(top * self.width, bottom * self.width);
+        let bytes = readback::read_buffer_range(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.gpu.weights,
+            range.clone(),
+        )
+        .map_err(|err| err.to_string())?;
+
+        let mut weights: Vec<f32> = unaligned(&bytes);
+        let species_count = self.species.len();
+        for row in top..bottom {
+            for column in 0..self.width {
+                let (dx, dy) = (column as f64 + 0.5 - x, row as f64 + 0.5 - y);
+                if dx * dx + dy * dy <= radius * radius {
+                    let pixel = ((row - top) * self.width + column) as usize;
+                    let weight = &mut weights[pixel * species_count + species];
+                    *weight = (*weight + amount as f32).max(0.0);
+                }
+            }
+        }
+
+        self.gpu.queue.write_buffer(
+            &self.gpu.weights,
+            range.start,
+            bytemuck::cast_slice(&weights),
+        );
+        Ok(())
+    }
+
+    fn clear(&self, species: Option<usize>) -> ScriptResult<()> {
+        let range = self.pixels(0, self.width * self.height);
+        let weights = match species {
+            Some(species) => {
+                let bytes = readback::read_buffer_range(
+                    &self.gpu.device,
+                    &self.gpu.queue,
+                    &self.gpu.weights,
+                    range,
+                )
+                .map_err(|err| err.to_string())?;
+
+                let mut weights: Vec<f32> = unaligned(&bytes);
+                for pixel in weights.chunks_exact_mut(self.species.len()) {
+                    pixel[species] = 0.0;
+                }
+                weights
+            }
+            None => vec![0.0; range.end as usize / mem::size_of::<f32>()],
+        };
+
+        self.gpu
+            .queue
+            .write_buffer(&self.gpu.weights, 0, bytemuck::cast_slice(&weights));
+        Ok(())
+    }
+
+    /// Places up to `count` agents in free slots within `radius`, returning how many fit.
+    fn spawn(
+        &mut self,
+        species: usize,
+        x: f64,
+        y: f64,
+        radius: f64,
+        count: i64,
+    ) -> ScriptResult<i64> {
+        let bytes = readback::read_buffer(&self.gpu.device, &self.gpu.queue, &self.gpu.agents)
+            .map_err(|err| err.to_string())?;
+        let agents: Vec<Agent> = unaligned(&bytes);
+        let free = agents
+            .iter()
+            .enumerate()
+            .filter(|(_, agent)| agent.alive == 0)
+            .map(|(slot, _)| slot)
+            .take(count.max(0) as usize)
+            .collect::<Vec<_>>();
+
+        let radius = radius as f32;
+        for &slot in &free {
+            let distance = radius * self.rng.gen::<f32>().sqrt();
+            let direction = self.rng.gen_range(0.0..2.0 * PI);
+
+            let agent = Agent {
+                position: [
+                    x as f32 + distance * direction.cos(),
+                    y as f32 + distance * direction.sin(),
+                ],
+                angle: self.rng.gen_range(0.0..2.0 * PI),
+                species: species as u32,
+                move_speed_scale: 1.0,
+                turn_speed_scale: 1.0,
+                sensor_angle_scale: 1.0,
+                sensor_offset_scale: 1.0,
+                energy: self.species[species].initial_energy,
+                alive: 1,
+                fitness: 0.0,
+                claim: 0,
+            };
+
+            self.gpu.queue.write_buffer(
+                &self.gpu.agents,
+                (slot * mem::size_of::<Agent>()) as wgpu::BufferAddress,
+                bytemuck::bytes_of(&agent),
+            );
+        }
+
+        Ok(free.len() as i64)
+    }
+}
+
+/// A Rhai script whose `on_start`, `on_step` and `on_key` functions are called by the simulation.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    host: Rc<RefCell<Host>>,
+}
+
+impl Script {
+    #[allow(clippy::too_many_arguments)]
+    pub fn load(
+        path: &Path,
+        gpu: Gpu,
+        width: u32,
+        height: u32,
+        names: &HashMap<String, usize>,
+        species: &[Species],
+        relations: &[u32],
+        seed: u64,
+    ) -> Result<Self> {
+        let source = fs::read_to_string(path)?;
+
+        let mut host = Host {
+            gpu,
+            width,
+            height,
+            names: names.clone(),
+            species: Vec::new(),
+            likes: Vec::new(),
+            hates: Vec::new(),
+            changed: false,
+            rng: StdRng::seed_from_u64(seed),
+        };
+        host.restore(species, relations);
+        let host = Rc::new(RefCell::new(host));
+
+        let mut engine = Engine::new();
+        register(&mut engine, &host);
+
+        let ast = engine
+            .compile(source)
+            .map_err(|err| anyhow!("{}: {err}", path.display()))?;
+
+        let mut scope = Scope::new();
+        scope.push_constant("WIDTH", width as i64);
+        scope.push_constant("HEIGHT", height as i64);
+        // Top level statements run once, so the hooks can use the variables they declare.
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| anyhow!("{}: {err}", path.display()))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            host,
+        })
+    }
+
+    /// Forgets the relations changed by `like`, `hate` and `ignore` when the simulation restores
+    /// its initial species and relations. Variables the script declared keep their values.
+    pub fn reset(&mut self, species: &[Species], relations: &[u32]) {
+        self.host.borrow_mut().restore(species, relations);
+    }
+
+    /// The relations the species currently index into, packed like the settings' relations.
+    pub fn relations(&self) -> Vec<u32> {
+        let host = self.host.borrow();
+        host.likes
+            .iter()
+            .zip(&host.hates)
+            .flat_map(|(likes, hates)| likes.iter().chain(hates))
+            .copied()
+            .collect()
+    }
+
+    pub fn on_start(&mut self, species: &mut [Species]) -> Result<()> {
+        self.call("on_start", (), species)
+    }
+
+    /// Called after every step with the number of steps so far and the simulated seconds.
+    pub fn on_step(&mut self, step: u64, time: f32, species: &mut [Species]) -> Result<()> {
+        self.call("on_step", (step as i64, time as f64), species)
+    }
+
+    /// Called with names like `"KeyG"` or `"Digit1"` for keys without a built-in action.
+    pub fn on_key(&mut self, key_code: KeyCode, species: &mut [Species]) -> Result<()> {
+        self.call("on_key", (format!("{key_code:?}"),), species)
+    }
+
+    /// Calls `name` if the script defines it, then copies back any species changes it made.
+    fn call(&mut self, name: &str, args: impl FuncArgs, species: &mut [Species]) -> Result<()> {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return Ok(());
+        }
+
+        self.host.borrow_mut().species.copy_from_slice(species);
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        );
+
+        let mut host = self.host.borrow_mut();
+        if host.changed {
+            species.copy_from_slice(&host.species);
+            host.changed = false;
+        }
+
+        result.map(|_| ()).map_err(|err| anyhow!("{name}: {err}"))
+    }
+}
+
+/// Copies read back bytes, which aren't guaranteed to be aligned for `T`.
+fn unaligned<T: bytemuck::Pod>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+fn register(engine: &mut Engine, host: &Rc<RefCell<Host>>) {
+    let h = host.clone();
+    engine.register_fn(
+        "get",
+        move |species: &str, field: &str| -> ScriptResult<f64> {
+            let mut host = h.borrow_mut();
+            let species = host.species_index(species)?;
+            Ok(*Host::field(field)?.get(&mut host.species[species]) as f64)
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn(
+        "set",
+        move |species: &str, field: &str, value: f64| -> ScriptResult<()> {
+            let mut host = h.borrow_mut();
+            let species = host.species_index(species)?;
+            *Host::field(field)?.get(&mut host.species[species]) = value as f32;
+            host.write_species();
+            Ok(())
+        },
+    );
+
+    for (name, relation) in [
+        ("like", Relation::Like),
+        ("hate", Relation::Hate),
+        ("ignore", Relation::Ignore),
+    ] {
+        let h = host.clone();
+        engine.register_fn(
+            name,
+            move |species: &str, other: &str| -> ScriptResult<()> {
+                let mut host = h.borrow_mut();
+                let species = host.species_index(species)?;
+                let other = host.species_index(other)?;
+                host.relate(species, other, relation);
+                Ok(())
+            },
+        );
+    }
+
+    let h = host.clone();
+    engine.register_fn(
+        "deposit",
+        move |species: &str, x: f64, y: f64, radius: f64, amount: f64| -> ScriptResult<()> {
+            let host = h.borrow();
+            host.deposit(host.species_index(species)?, x, y, radius, amount)
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn("clear", move || -> ScriptResult<()> {
+        h.borrow().clear(None)
+    });
+
+    let h = host.clone();
+    engine.register_fn("clear", move |species: &str| -> ScriptResult<()> {
+        let host = h.borrow();
+        host.clear(Some(host.species_index(species)?))
+    });
+
+    let h = host.clone();
+    engine.register_fn(
+        "spawn_agents",
+        move |species: &str, x: f64, y: f64, radius: f64, count: i64| -> ScriptResult<i64> {
+            let mut host = h.borrow_mut();
+            let species = host.species_index(species)?;
+            host.spawn(species, x, y, radius, count)
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn(
+        "concentration",
+        move |species: &str, x: f64, y: f64| -> ScriptResult<f64> {
+            let host = h.borrow();
+            host.concentration(host.species_index(species)?, x, y)
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn("population", move |species: &str| -> ScriptResult<i64> {
+        let host = h.borrow();
+        host.population(host.species_index(species)?)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::util::DeviceExt;
+
+    use super::*;
+
+    const WIDTH: u32 = 4;
+    const HEIGHT: u32 = 3;
+
+    /// Buffers for two species, `a` liking itself and hating `b`, and `b` liking itself, with
+    /// the species and relations they hold.
+    fn gpu() -> Option<(Gpu, [Species; 2], [u32; 8])> {
+        let instance = wgpu::Instance::default();
+        let Some(adapter) = pollster::block_on(instance.request_adapter(&Default::default()))
+        else {
+            eprintln!("no adapter, skipping");
+            return None;
+        };
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&Default::default(), None)).unwrap();
+
+        let storage = |contents: &[u8]| {
+            Arc::new(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents,
+                    usage: wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                }),
+            )
+        };
+
+        let mut species: [Species; 2] = bytemuck::Zeroable::zeroed();
+        species[0].like_length = 1;
+        species[0].hate_index = 1;
+        species[0].hate_length = 1;
+        species[1].like_index = 2;
+        species[1].like_length = 1;
+        species[1].initial_energy = 5.0;
+        let relations = [0, 1, 1, 0, 0, 0, 0, 0];
+
+        let mut agents: [Agent; 3] = bytemuck::Zeroable::zeroed();
+        agents[1].alive = 1;
+        agents[1].species = 1;
+
+        let gpu = Gpu {
+            species: storage(bytemuck::cast_slice(&species)),
+            relations: storage(bytemuck::cast_slice(&relations)),
+            agents: storage(bytemuck::cast_slice(&agents)),
+            weights: storage(bytemuck::cast_slice(
+                &[1.0f32; (WIDTH * HEIGHT * 2) as usize],
+            )),
+            lifecycle: storage(bytemuck::cast_slice(&[0u32, 0, 7, 9])),
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+        };
+
+        Some((gpu, species, relations))
+    }
+
+    fn names() -> HashMap<String, usize> {
+        HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)])
+    }
+
+    fn host() -> Option<Host> {
+        let (gpu, species, relations) = gpu()?;
+        let mut host = Host {
+            gpu,
+            width: WIDTH,
+            height: HEIGHT,
+            names: names(),
+            species: Vec::new(),
+            likes: Vec::new(),
+            hates: Vec::new(),
+            changed: false,
+            rng: StdRng::seed_from_u64(1),
+        };
+        host.restore(&species, &relations);
+        Some(host)
+    }
+
+    fn read<T: bytemuck::Pod>(host: &Host, buffer: &wgpu::Buffer) -> Vec<T> {
+        unaligned(&readback::read_buffer(&host.gpu.device, &host.gpu.queue, buffer).unwrap())
+    }
+
+    #[test]
+    fn field_names_parse() {
+        assert!(matches!(Host::field("move_speed"), Ok(Field::MoveSpeed)));
+        assert!(matches!(
+            Host::field("sensor_angle"),
+            Ok(Field::SensorAngle)
+        ));
+
+        let err = Host::field("speed").err().unwrap();
+        assert!(err.to_string().contains("unknown field speed"));
+    }
+
+    #[test]
+    fn relate_rebuilds_packed_relations() {
+        let Some(mut host) = host() else {
+            return;
+        };
+        assert_eq!(host.likes, [vec![0], vec![1]]);
+        assert_eq!(host.hates, [vec![1], vec![]]);
+
+        host.relate(0, 1, Relation::Like);
+        host.relate(1, 0, Relation::Hate);
+        host.relate(1, 1, Relation::Ignore);
+        assert!(host.changed);
+        assert_eq!(host.likes, [vec![0, 1], vec![]]);
+        assert_eq!(host.hates, [vec![], vec![0]]);
+
+        let species: Vec<Species> = read(&host, &host.gpu.species);
+        let layout = species
+            .iter()
+            .map(|s| [s.like_index, s.like_length, s.hate_index, s.hate_length])
+            .collect::<Vec<_>>();
+        assert_eq!(layout, [[0, 2, 2, 0], [2, 0, 2, 1]]);
+
+        let relations: Vec<u32> = read(&host, &host.gpu.relations);
+        assert_eq!(relations[..3], [0, 1, 0]);
+    }
+
+    #[test]
+    fn deposit_and_clear_edit_pheromone() {
+        let Some(host) = host() else {
+            return;
+        };
+
+        host.deposit(1, 1.5, 1.5, 0.6, 0.5).unwrap();
+        host.deposit(0, 2.5, 1.5, 0.6, -5.0).unwrap();
+        assert_eq!(host.concentration(1, 1.0, 1.0).unwrap(), 1.5);
+        assert_eq!(host.concentration(0, 2.0, 1.0).unwrap(), 0.0);
+        assert_eq!(host.concentration(0, -1.0, 1.0).unwrap(), 0.0);
+
+        let weights: Vec<f32> = read(&host, &host.gpu.weights);
+        let changed = weights.iter().filter(|&&weight| weight != 1.0).count();
+        assert_eq!(changed, 2);
+
+        host.clear(Some(0)).unwrap();
+        let weights: Vec<f32> = read(&host, &host.gpu.weights);
+        assert!(weights.chunks(2).all(|pixel| pixel[0] == 0.0));
+        assert_eq!(weights[(1 + WIDTH as usize) * 2 + 1], 1.5);
+
+        host.clear(None).unwrap();
+        let weights: Vec<f32> = read(&host, &host.gpu.weights);
+        assert!(weights.iter().all(|&weight| weight == 0.0));
+
+        assert_eq!(host.population(1).unwrap(), 9);
+    }
+
+    #[test]
+    fn spawn_fills_free_slots() {
+        let Some(mut host) = host() else {
+            return;
+        };
+
+        assert_eq!(host.spawn(1, 2.0, 1.0, 0.5, 5).unwrap(), 2);
+
+        let agents: Vec<Agent> = read(&host, &host.gpu.agents);
+        assert!(agents
+            .iter()
+            .all(|agent| agent.alive == 1 && agent.species == 1));
+        assert_eq!(agents[1].position, [0.0, 0.0]);
+
+        for agent in [agents[0], agents[2]] {
+            let [x, y] = agent.position;
+            assert!(((x - 2.0).powi(2) + (y - 1.0).powi(2)).sqrt() <= 0.5 + 1e-5);
+            assert_eq!(agent.energy, 5.0);
+        }
+
+        assert_eq!(host.spawn(0, 2.0, 1.0, 0.5, 5).unwrap(), 0);
+    }
+
+    #[test]
+    fn reset_restores_relations_after_on_start() {
+        let Some((gpu, mut species, relations)) = gpu() else {
+            return;
+        };
+        let path = std::env::temp_dir().join(format!("reset_{}.rhai", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+                fn on_start() { like("a", "b"); }
+                fn on_key(key) { ignore("a", "a"); hate("b", "a"); }
+            "#,
+        )
+        .unwrap();
+        let mut script =
+            Script::load(&path, gpu, WIDTH, HEIGHT, &names(), &species, &relations, 1).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        script.on_start(&mut species).unwrap();
+        let initial_species = species;
+        let initial_relations = script.relations();
+        assert_eq!(initial_relations, [0, 1, 1]);
+        assert_eq!(species[0].hate_index, 2);
+
+        script.on_key(KeyCode::KeyG, &mut species).unwrap();
+        assert_eq!(script.relations(), [1, 1, 0]);
+
+        script.reset(&initial_species, &initial_relations);
+        assert_eq!(script.relations(), initial_relations);
+
+        // Relating again builds on the state after `on_start`, not the settings' relations.
+        script.on_key(KeyCode::KeyG, &mut species).unwrap();
+        assert_eq!(script.relations(), [1, 1, 0]);
+    }
+}
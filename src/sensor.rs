@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Pixels around each sensor position that count towards its reading.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorShape {
+    /// All pixels within `sensor_size` along both axes.
+    #[default]
+    Square,
+    /// Only pixels within a distance of `sensor_size`.
+    Circle,
+}
+
+/// How much each pixel of the footprint contributes to a reading.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorWeighting {
+    #[default]
+    Uniform,
+    /// Falling off linearly to zero at one pixel beyond `sensor_size`.
+    Linear,
+}
+
+pub fn default_sensors() -> u32 {
+    3
+}
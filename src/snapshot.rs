@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+
+const MAGIC: &[u8; 8] = b"PHEROSNP";
+const VERSION: u32 = 6;
+
+/// Everything needed to continue a run: the resolved settings, the seed, the simulated time and the
+/// raw GPU state, whose species and relations include changes made since the start.
+///
+/// Layout (little endian): magic, version, seed, simulated time, then the settings TOML, the
+/// species, relation, agent and weight buffers as length-prefixed byte blocks, followed by a CRC32
+/// of everything before it.
+pub struct Snapshot {
+    pub seed: u64,
+    /// Simulated seconds, which the timeline continues from.
+    pub time: f32,
+    pub settings: String,
+    pub species: Vec<u8>,
+    pub relations: Vec<u8>,
+    pub agents: Vec<u8>,
+    pub weights: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+
+        for block in [
+            self.settings.as_bytes(),
+            &self.species,
+            &self.relations,
+            &self.agents,
+            &self.weights,
+        ] {
+            bytes.extend_from_slice(&(block.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(block);
+        }
+
+        let checksum = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < MAGIC.len() + 4 {
+            bail!("{} is too short to be a snapshot", path.display());
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32fast::hash(body).to_le_bytes() != checksum {
+            bail!("{} has an invalid checksum", path.display());
+        }
+
+        let mut reader = Cursor::new(body);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("{} is not a snapshot", path.display());
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            bail!("unsupported snapshot version {version}, expected {VERSION}");
+        }
+
+        let seed = u64::from_le_bytes(read_array(&mut reader)?);
+        let time = f32::from_le_bytes(read_array(&mut reader)?);
+        let settings = String::from_utf8(read_block(&mut reader)?)?;
+        let species = read_block(&mut reader)?;
+        let relations = read_block(&mut reader)?;
+        let agents = read_block(&mut reader)?;
+        let weights = read_block(&mut reader)?;
+
+        Ok(Self {
+            seed,
+            time,
+            settings,
+            species,
+            relations,
+            agents,
+            weights,
+        })
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut Cursor<&[u8]>) -> Result<[u8; N]> {
+    let mut array = [0; N];
+    reader.read_exact(&mut array)?;
+    Ok(array)
+}
+
+fn read_block(reader: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let len = u64::from_le_bytes(read_array(reader)?) as usize;
+    let remaining = reader.get_ref().len() - reader.position() as usize;
+
+    if len > remaining {
+        bail!("snapshot block of {len} bytes is truncated");
+    }
+
+    let mut block = vec![0; len];
+    reader.read_exact(&mut block)?;
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            seed: 42,
+            time: 12.5,
+            settings: "width = 8\n".to_string(),
+            species: vec![3; 32],
+            relations: vec![1, 0, 0, 0],
+            agents: (0..48).collect(),
+            weights: vec![7; 16],
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}_{}.snapshot", std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = path("round_trip");
+        snapshot().write(&path).unwrap();
+        let read = Snapshot::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let expected = snapshot();
+        assert_eq!(read.seed, expected.seed);
+        assert_eq!(read.time, expected.time);
+        assert_eq!(read.settings, expected.settings);
+        assert_eq!(read.species, expected.species);
+        assert_eq!(read.relations, expected.relations);
+        assert_eq!(read.agents, expected.agents);
+        assert_eq!(read.weights, expected.weights);
+    }
+
+    #[test]
+    fn flipped_byte_fails_checksum() {
+        let path = path("flipped_byte");
+        snapshot().write(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[40] ^= 1;
+        fs::write(&path, bytes).unwrap();
+
+        let err = Snapshot::read(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("invalid checksum"));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let path = path("unknown_version");
+        snapshot().write(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let body = bytes.len() - 4;
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let checksum = crc32fast::hash(&bytes[..body]);
+        bytes[body..].copy_from_slice(&checksum.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+
+        let err = Snapshot::read(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "unsupported snapshot version {}, expected {VERSION}",
+                VERSION + 1
+            )
+        );
+    }
+}
@@ -0,0 +1,33 @@
+// Prepended to every shader reading the species buffer, matches `Species` in main.rs.
+
+struct Species {
+    color: vec3<f32>,
+    amount: u32,
+    move_speed: f32,
+    turn_speed: f32,
+    sensor_angle: f32,
+    sensor_offset: f32,
+    sensor_size: i32,
+    decay_rate: f32,
+    diffuse_rate: f32,
+    like_index: u32,
+    like_length: u32,
+    hate_index: u32,
+    hate_length: u32,
+    initial_energy: f32,
+    drain_rate: f32,
+    gain_rate: f32,
+    reproduce_energy: f32,
+    convert_to: u32,
+    convert_threshold: f32,
+    convert_probability: f32,
+    mutation: f32,
+    sensors: u32,
+    sensor_shape: u32,
+    sensor_weighting: u32,
+    sensor_bilinear: u32,
+    steering: u32,
+    steering_temperature: f32,
+    crowding_threshold: f32,
+    exclusive: u32,
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// How agents turn in response to their sensor readings.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Steering {
+    /// Straight if forward is best, a random turn if it is worst, otherwise toward the best side.
+    #[default]
+    Classic,
+    /// Turn in proportion to the normalized difference between the left and right sensors.
+    Gradient,
+    /// Turn toward a sensor picked with probability `exp(reading / steering_temperature)`.
+    Softmax,
+    /// Like classic, but toward the weaker side once forward reads above `crowding_threshold`.
+    AvoidCrowding,
+}
+
+pub fn default_temperature() -> f32 {
+    0.1
+}
+
+pub fn default_crowding_threshold() -> f32 {
+    5.0
+}
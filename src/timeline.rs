@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::Species;
+
+/// How a track moves between two keyframes.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    Linear,
+    /// Eases in and out of each keyframe.
+    Smoothstep,
+    /// Holds each keyframe's value until the next one.
+    Step,
+}
+
+/// Species parameters a track can animate.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    MoveSpeed,
+    TurnSpeed,
+    SensorAngle,
+    SensorOffset,
+    DecayRate,
+    DiffuseRate,
+    DrainRate,
+    GainRate,
+    ReproduceEnergy,
+    ConvertThreshold,
+    ConvertProbability,
+    Mutation,
+}
+
+impl Field {
+    pub fn get(self, species: &mut Species) -> &mut f32 {
+        match self {
+            Field::MoveSpeed => &mut species.move_speed,
+            Field::TurnSpeed => &mut species.turn_speed,
+            Field::SensorAngle => &mut species.sensor_angle,
+            Field::SensorOffset => &mut species.sensor_offset,
+            Field::DecayRate => &mut species.decay_rate,
+            Field::DiffuseRate => &mut species.diffuse_rate,
+            Field::DrainRate => &mut species.drain_rate,
+            Field::GainRate => &mut species.gain_rate,
+            Field::ReproduceEnergy => &mut species.reproduce_energy,
+            Field::ConvertThreshold => &mut species.convert_threshold,
+            Field::ConvertProbability => &mut species.convert_probability,
+            Field::Mutation => &mut species.mutation,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Simulated seconds since the start of the run.
+    pub time: f32,
+    pub value: f32,
+}
+
+/// Keyframes for one field of one species, holding the first and last value outside of them.
+#[derive(Serialize, Deserialize)]
+pub struct TrackSettings {
+    pub species: String,
+    pub field: Field,
+    #[serde(default = "default_interpolation")]
+    pub interpolation: Interpolation,
+    pub keyframes: Vec<Keyframe>,
+}
+
+fn default_interpolation() -> Interpolation {
+    Interpolation::Linear
+}
+
+struct Track {
+    species: usize,
+    field: Field,
+    interpolation: Interpolation,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    fn evaluate(&self, time: f32) -> f32 {
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+
+        if next == 0 {
+            return self.keyframes[0].value;
+        }
+        let from = self.keyframes[next - 1];
+        let Some(&to) = self.keyframes.get(next) else {
+            return from.value;
+        };
+
+        let t = (time - from.time) / (to.time - from.time);
+        let t = match self.interpolation {
+            Interpolation::Linear => t,
+            Interpolation::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Interpolation::Step => 0.0,
+        };
+
+        from.value + (to.value - from.value) * t
+    }
+}
+
+/// Species parameters animated over simulated time, evaluated on the CPU every step.
+pub struct Timeline {
+    tracks: Vec<Track>,
+}
+
+impl Timeline {
+    pub fn new(settings: &[TrackSettings], species_map: &HashMap<String, usize>) -> Result<Self> {
+        let tracks = settings
+            .iter()
+            .filter(|track| !track.keyframes.is_empty())
+            .map(|track| {
+                let species = *species_map.get(&track.species).ok_or_else(|| {
+                    anyhow!("timeline track for unknown species {}", track.species)
+                })?;
+
+                let mut keyframes = track.keyframes.clone();
+                keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+                Ok(Track {
+                    species,
+                    field: track.field,
+                    interpolation: track.interpolation,
+                    keyframes,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { tracks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Sets every animated field to its value at `time`.
+    pub fn apply(&self, time: f32, species: &mut [Species]) {
+        for track in &self.tracks {
+            *track.field.get(&mut species[track.species]) = track.evaluate(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(interpolation: Interpolation) -> Track {
+        Track {
+            species: 0,
+            field: Field::MoveSpeed,
+            interpolation,
+            keyframes: [(1.0, 10.0), (3.0, 20.0), (4.0, 0.0)]
+                .map(|(time, value)| Keyframe { time, value })
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn evaluate_interpolates_between_keyframes() {
+        use Interpolation::*;
+
+        for (interpolation, time, expected) in [
+            (Linear, 0.0, 10.0),
+            (Linear, 1.0, 10.0),
+            (Linear, 1.5, 12.5),
+            (Linear, 2.0, 15.0),
+            (Linear, 3.5, 10.0),
+            (Linear, 4.0, 0.0),
+            (Linear, 9.0, 0.0),
+            (Smoothstep, -1.0, 10.0),
+            (Smoothstep, 1.5, 11.5625),
+            (Smoothstep, 2.0, 15.0),
+            (Smoothstep, 2.5, 18.4375),
+            (Smoothstep, 5.0, 0.0),
+            (Step, 0.5, 10.0),
+            (Step, 2.9, 10.0),
+            (Step, 3.0, 20.0),
+            (Step, 3.9, 20.0),
+            (Step, 4.5, 0.0),
+        ] {
+            let value = track(interpolation).evaluate(time);
+            assert!(
+                (value - expected).abs() < 1e-5,
+                "{time}: expected {expected}, got {value}"
+            );
+        }
+    }
+}
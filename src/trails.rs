@@ -0,0 +1,145 @@
+use wgpu::{include_wgsl, StoreOp};
+
+use crate::agent_render::{AgentMode, AgentRenderer};
+
+const TRAILS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// A long exposure of the agents' positions, fading on its own independently of the pheromones.
+pub struct Trails {
+    view: wgpu::TextureView,
+    fade_pipeline: wgpu::RenderPipeline,
+    deposit: AgentRenderer,
+    fade_rate: f32,
+}
+
+impl Trails {
+    pub fn new(
+        device: &wgpu::Device,
+        species_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+        fade_rate: f32,
+        intensity: f32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TRAILS_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let shader = device.create_shader_module(include_wgsl!("trails.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let fade_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_fade",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: TRAILS_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::OneMinusConstant,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+
+        let deposit = AgentRenderer::new(
+            device,
+            TRAILS_FORMAT,
+            additive,
+            species_buffer,
+            [width as f32, height as f32],
+            1.0,
+            intensity,
+        );
+
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            fade_pipeline,
+            deposit,
+            fade_rate,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        agents_buffer: &wgpu::Buffer,
+        agent_count: u32,
+        time_delta: f32,
+    ) {
+        let fade = 1.0 - (-self.fade_rate * time_delta).exp() as f64;
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.fade_pipeline);
+        render_pass.set_blend_constant(wgpu::Color {
+            r: fade,
+            g: fade,
+            b: fade,
+            a: fade,
+        });
+        render_pass.draw(0..3, 0..1);
+
+        self.deposit.draw(
+            &mut render_pass,
+            agents_buffer,
+            agent_count,
+            AgentMode::Points,
+        );
+    }
+}
@@ -0,0 +1,11 @@
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Blended with the blend constant to scale what is already there.
+@fragment
+fn fs_fade() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,636 @@
+use std::{f32::consts::PI, iter, mem, time::Instant};
+
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use wgpu::{include_wgsl, util::DeviceExt, StoreOp};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+
+use crate::{hud::Hud, Settings, Species};
+
+/// Channels of the volume texture, one per species.
+const MAX_SPECIES: usize = 4;
+
+const VOLUME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeSettings {
+    /// Cells along x and y, `depth` along z.
+    pub width: u32,
+    pub height: u32,
+    /// Ray marching samples per pixel.
+    pub steps: u32,
+    /// Opacity per unit of pheromone and of the volume's longest side.
+    pub density: f32,
+    /// Initial camera distance from the center, in units of the volume's longest side.
+    pub distance: f32,
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        Self {
+            width: 128,
+            height: 128,
+            steps: 128,
+            density: 20.0,
+            distance: 2.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Agent {
+    position: [f32; 3],
+    species: u32,
+    /// Unit vector the agent moves along.
+    direction: [f32; 3],
+    _p0: u32,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    size: [u32; 3],
+    time_delta: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Camera {
+    eye: [f32; 3],
+    steps: u32,
+    right: [f32; 3],
+    density: f32,
+    up: [f32; 3],
+    species_count: u32,
+    forward: [f32; 3],
+    _p0: u32,
+    extent: [f32; 3],
+    _p1: u32,
+    colors: [[f32; 4]; MAX_SPECIES],
+}
+
+/// Camera circling the volume's center, rotated by dragging with the left mouse button and moved
+/// closer or further with the wheel.
+struct OrbitCamera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    dragging: bool,
+    cursor: Option<PhysicalPosition<f64>>,
+}
+
+impl OrbitCamera {
+    fn handle(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => self.dragging = *state == ElementState::Pressed,
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some(last)) = (self.dragging, self.cursor) {
+                    self.yaw -= (position.x - last.x) as f32 * 0.01;
+                    self.pitch = (self.pitch + (position.y - last.y) as f32 * 0.01)
+                        .clamp(-PI / 2.0 + 0.01, PI / 2.0 - 0.01);
+                }
+                self.cursor = Some(*position);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                self.distance = (self.distance * 0.9f32.powf(lines)).max(0.1);
+            }
+            _ => {}
+        }
+    }
+
+    /// View vectors for a vertical field of view of 60 degrees.
+    fn uniform(&self, aspect: f32) -> ([f32; 3], [f32; 3], [f32; 3], [f32; 3]) {
+        let eye = [
+            self.distance * self.pitch.cos() * self.yaw.sin(),
+            self.distance * self.pitch.sin(),
+            self.distance * self.pitch.cos() * self.yaw.cos(),
+        ];
+        let forward = normalize(eye.map(|c| -c));
+        let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+        let up = cross(right, forward);
+
+        let scale = (PI / 6.0).tan();
+        (
+            eye,
+            right.map(|c| c * scale * aspect),
+            up.map(|c| c * scale),
+            forward,
+        )
+    }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2])
+        .sqrt()
+        .max(f32::EPSILON);
+    v.map(|c| c / length)
+}
+
+/// Places `amounts[i]` agents of each species in a sphere around the volume's center, each heading
+/// toward it.
+fn spawn(amounts: &[u32], size: [u32; 3], seed: u64) -> Vec<Agent> {
+    let [size_x, size_y, size_z] = size.map(|c| c as f32);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let radius = size_x.min(size_y).min(size_z) * 0.4;
+    let center = [size_x / 2.0, size_y / 2.0, size_z / 2.0];
+    let mut agents = Vec::new();
+
+    for (i, &amount) in amounts.iter().enumerate() {
+        for _ in 0..amount {
+            let offset = loop {
+                let offset = [(); 3].map(|_| rng.gen_range(-radius..radius));
+                if offset.iter().map(|c| c * c).sum::<f32>() <= radius * radius {
+                    break offset;
+                }
+            };
+
+            agents.push(Agent {
+                position: [0, 1, 2].map(|axis| center[axis] + offset[axis]),
+                species: i as u32,
+                direction: normalize(offset.map(|c| -c)),
+                _p0: 0,
+            });
+        }
+    }
+
+    agents
+}
+
+/// Runs the volumetric simulation in place of the 2D one until the window closes.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    event_loop: EventLoop<()>,
+    window: &Window,
+    surface: &wgpu::Surface,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    format: wgpu::TextureFormat,
+    settings: &Settings,
+    depth: u32,
+    species: &[Species],
+    relations: &[u32],
+    seed: u64,
+) -> Result<()> {
+    if species.len() > MAX_SPECIES {
+        bail!("the volumetric simulation supports at most {MAX_SPECIES} species");
+    }
+
+    let volume = &settings.volume;
+    let size = [volume.width, volume.height, depth];
+    if size.contains(&0) {
+        bail!("the volume's width, height and depth must be positive");
+    }
+
+    let [size_x, size_y, size_z] = size.map(|c| c as f32);
+
+    let amounts = settings
+        .species
+        .iter()
+        .map(|s| s.amount)
+        .collect::<Vec<_>>();
+    let agents = spawn(&amounts, size, seed);
+    if agents.is_empty() {
+        bail!("the volumetric simulation needs at least one species with a nonzero amount");
+    }
+
+    let agents_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&agents),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let species_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(species),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    // A binding can't be empty, even if no species has relations.
+    let relations = if relations.is_empty() {
+        &[0]
+    } else {
+        relations
+    };
+    let relations_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(relations),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: mem::size_of::<Params>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: mem::size_of::<Camera>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: size[2],
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: VOLUME_FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let buffer_entry =
+        |binding: u32, ty: wgpu::BufferBindingType, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+    let compute_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                buffer_entry(
+                    0,
+                    wgpu::BufferBindingType::Uniform,
+                    wgpu::ShaderStages::COMPUTE,
+                ),
+                buffer_entry(
+                    1,
+                    wgpu::BufferBindingType::Storage { read_only: true },
+                    wgpu::ShaderStages::COMPUTE,
+                ),
+                buffer_entry(
+                    2,
+                    wgpu::BufferBindingType::Storage { read_only: true },
+                    wgpu::ShaderStages::COMPUTE,
+                ),
+                buffer_entry(
+                    3,
+                    wgpu::BufferBindingType::Storage { read_only: false },
+                    wgpu::ShaderStages::COMPUTE,
+                ),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadWrite,
+                        format: VOLUME_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+    let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &compute_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: species_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: relations_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: agents_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+        ],
+    });
+
+    let compute_shader = device.create_shader_module(include_species_wgsl!("volume.wgsl"));
+    let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&compute_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let compute_pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point,
+        })
+    };
+    let agents_pipeline = compute_pipeline("main_agents");
+    let diffuse_pipeline = compute_pipeline("main_diffuse");
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let render_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                buffer_entry(
+                    0,
+                    wgpu::BufferBindingType::Uniform,
+                    wgpu::ShaderStages::FRAGMENT,
+                ),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+    let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &render_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    });
+
+    let render_shader = device.create_shader_module(include_wgsl!("volume_render.wgsl"));
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&render_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &render_shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &render_shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+
+    let longest = size_x.max(size_y).max(size_z);
+    let extent = [size_x, size_y, size_z].map(|c| c / longest * 0.5);
+    let mut colors = [[0.0; 4]; MAX_SPECIES];
+    for (color, spec) in colors.iter_mut().zip(species) {
+        *color = [spec.color[0], spec.color[1], spec.color[2], 1.0];
+    }
+
+    let mut camera = OrbitCamera {
+        yaw: 0.6,
+        pitch: 0.4,
+        distance: volume.distance,
+        dragging: false,
+        cursor: None,
+    };
+
+    let window_size = window.inner_size();
+    let aspect = window_size.width as f32 / window_size.height.max(1) as f32;
+    let agent_workgroups = (agents.len() as u32).div_ceil(64);
+    let volume_workgroups = size.map(|c| c.div_ceil(4));
+
+    let mut hud = Hud::new();
+    let mut start = Instant::now();
+
+    event_loop.run(|event, target| match event {
+        Event::WindowEvent {
+            event:
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            state: ElementState::Pressed,
+                            physical_key: PhysicalKey::Code(KeyCode::Escape),
+                            ..
+                        },
+                    ..
+                },
+            window_id,
+        } if window_id == window.id() => target.exit(),
+        Event::WindowEvent {
+            window_id,
+            event: WindowEvent::RedrawRequested,
+        } if window_id == window.id() => {
+            let time_delta = start.elapsed().as_secs_f32();
+            start = Instant::now();
+            queue.write_buffer(
+                &params_buffer,
+                0,
+                bytemuck::cast_slice(&[Params { size, time_delta }]),
+            );
+
+            let (eye, right, up, forward) = camera.uniform(aspect);
+            let uniform = Camera {
+                eye,
+                steps: volume.steps,
+                right,
+                density: volume.density,
+                up,
+                species_count: species.len() as u32,
+                forward,
+                _p0: 0,
+                extent,
+                _p1: 0,
+                colors,
+            };
+            queue.write_buffer(&camera_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+                compute_pass.set_bind_group(0, &compute_bind_group, &[]);
+                compute_pass.set_pipeline(&agents_pipeline);
+                compute_pass.dispatch_workgroups(agent_workgroups, 1, 1);
+                compute_pass.set_pipeline(&diffuse_pipeline);
+                let [x, y, z] = volume_workgroups;
+                compute_pass.dispatch_workgroups(x, y, z);
+            }
+
+            let output = surface.get_current_texture().unwrap();
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    ..Default::default()
+                });
+
+                render_pass.set_pipeline(&render_pipeline);
+                render_pass.set_bind_group(0, &render_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+
+            queue.submit(iter::once(encoder.finish()));
+            hud.frame(1);
+
+            if settings.hud && hud.due() {
+                window.set_title(&hud.title(&[], None));
+            }
+
+            output.present();
+        }
+        Event::WindowEvent { window_id, event } if window_id == window.id() => {
+            camera.handle(&event);
+        }
+        Event::AboutToWait => {
+            window.request_redraw();
+        }
+        _ => {}
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    #[test]
+    fn camera_looks_at_the_center() {
+        let scale = (PI / 6.0).tan();
+
+        for (yaw, pitch) in [(0.0, 0.0), (1.0, 0.5), (-2.5, -1.2), (PI, 1.5)] {
+            let camera = OrbitCamera {
+                yaw,
+                pitch,
+                distance: 3.0,
+                dragging: false,
+                cursor: None,
+            };
+            let (eye, right, up, forward) = camera.uniform(2.0);
+            let right = right.map(|c| c / (scale * 2.0));
+            let up = up.map(|c| c / scale);
+
+            assert!((dot(eye, eye).sqrt() - 3.0).abs() < 1e-5);
+            assert!((dot(forward, eye) + 3.0).abs() < 1e-5);
+            for (a, b) in [(right, up), (up, forward), (forward, right)] {
+                assert!(dot(a, b).abs() < 1e-5);
+            }
+            for v in [right, up, forward] {
+                assert!((dot(v, v) - 1.0).abs() < 1e-5);
+            }
+            assert!(up[1] >= 0.0);
+        }
+    }
+
+    #[test]
+    fn agents_start_in_a_sphere_heading_to_the_center() {
+        let size = [40, 60, 20];
+        let agents = spawn(&[100, 0, 50], size, 7);
+
+        assert_eq!(agents.len(), 150);
+        assert!(agents[..100].iter().all(|agent| agent.species == 0));
+        assert!(agents[100..].iter().all(|agent| agent.species == 2));
+
+        let center = [20.0, 30.0, 10.0];
+        for agent in &agents {
+            let offset = [0, 1, 2].map(|axis| center[axis] - agent.position[axis]);
+            assert!(dot(offset, offset).sqrt() <= 20.0 * 0.4 + 1e-4);
+            assert!((dot(agent.direction, agent.direction) - 1.0).abs() < 1e-4);
+            assert!(dot(agent.direction, offset) >= 0.0);
+        }
+
+        let again = spawn(&[100, 0, 50], size, 7);
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&agents),
+            bytemuck::cast_slice::<_, u8>(&again)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+struct Agent {
+    position: vec3<f32>,
+    species: u32,
+    direction: vec3<f32>,
+}
+
+struct Params {
+    size: vec3<u32>,
+    time_delta: f32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> params: Params;
+
+@group(0)
+@binding(1)
+var<storage> species: array<Species>;
+
+@group(0)
+@binding(2)
+var<storage> relations: array<u32>;
+
+@group(0)
+@binding(3)
+var<storage, read_write> agents: array<Agent>;
+
+// Concentration of each species in one channel.
+@group(0)
+@binding(4)
+var volume: texture_storage_3d<rgba32float, read_write>;
+
+const PI: f32 = 3.1415;
+
+fn hash(state: u32) -> u32 {
+    var hash = state;
+    hash ^= 2747636419u;
+    hash *= 2654435769u;
+    hash ^= hash >> 16u;
+    hash *= 2654435769u;
+    hash ^= hash >> 16u;
+    hash *= 2654435769u;
+    return hash;
+}
+
+fn random(seed: u32) -> f32 {
+    return f32(hash(seed)) / 4294967295.0;
+}
+
+// Wraps a cell around the faces of the volume, like agent positions.
+fn wrap(cell: vec3<i32>) -> vec3<i32> {
+    let size = vec3<i32>(params.size);
+    return (cell % size + size) % size;
+}
+
+// Liked minus hated pheromone in the cube of `sensor_size` around a position.
+fn sense(spec: Species, position: vec3<f32>) -> f32 {
+    let size = spec.sensor_size;
+    var sum = 0.0;
+
+    for (var x = -size; x <= size; x++) {
+        for (var y = -size; y <= size; y++) {
+            for (var z = -size; z <= size; z++) {
+                let cell = wrap(vec3<i32>(floor(position)) + vec3<i32>(x, y, z));
+                let weights = textureLoad(volume, cell);
+
+                let like_end = spec.like_index + spec.like_length;
+                for (var like_index = spec.like_index; like_index < like_end; like_index++) {
+                    sum += weights[relations[like_index]];
+                }
+
+                let hate_end = spec.hate_index + spec.hate_length;
+                for (var hate_index = spec.hate_index; hate_index < hate_end; hate_index++) {
+                    sum -= weights[relations[hate_index]];
+                }
+            }
+        }
+    }
+
+    return sum;
+}
+
+// Sense forward and on a cone of `sensor_angle` around it, turn toward the best sensor, move and
+// deposit, wrapping around the faces of the volume.
+@compute
+@workgroup_size(64)
+fn main_agents(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&agents)) {
+        return;
+    }
+
+    let agent = agents[i];
+    let spec = species[agent.species];
+    let forward = agent.direction;
+
+    // Any two axes perpendicular to the heading span the cone.
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(forward.y) > 0.9) {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let u = normalize(cross(forward, helper));
+    let v = cross(forward, u);
+
+    let weight_forward = sense(spec, agent.position + forward * spec.sensor_offset);
+    var best = -1e30;
+    var worst = 1e30;
+    var best_direction = forward;
+
+    for (var k = 0u; k < 4u; k++) {
+        let around = f32(k) * PI * 0.5;
+        let direction = cos(spec.sensor_angle) * forward + sin(spec.sensor_angle) * (cos(around) * u + sin(around) * v);
+        let weight = sense(spec, agent.position + direction * spec.sensor_offset);
+
+        if (weight > best) {
+            best = weight;
+            best_direction = direction;
+        }
+        worst = min(worst, weight);
+    }
+
+    let seed = hash(i) ^ bitcast<u32>(agent.position.x) ^ hash(bitcast<u32>(agent.position.z));
+    let max_turn = random(seed) * spec.turn_speed * 2.0 * PI * params.time_delta;
+
+    // Keep going if forward is best, pick a random sensor if it is worst, otherwise the best one.
+    var target_direction = best_direction;
+    if (weight_forward > best) {
+        target_direction = forward;
+    } else if (weight_forward < worst) {
+        let around = random(seed + 1u) * 2.0 * PI;
+        target_direction = cos(spec.sensor_angle) * forward + sin(spec.sensor_angle) * (cos(around) * u + sin(around) * v);
+    }
+
+    let t = clamp(max_turn / max(spec.sensor_angle, 0.0001), 0.0, 1.0);
+    let direction = normalize(mix(forward, target_direction, t));
+
+    let size = vec3<f32>(params.size);
+    var position = agent.position + direction * spec.move_speed * params.time_delta;
+    position = position - floor(position / size) * size;
+    position = min(position, size - 0.001);
+
+    let cell = vec3<i32>(position);
+    var weights = textureLoad(volume, cell);
+    weights[agent.species] = 1.0;
+    textureStore(volume, cell, weights);
+
+    agents[i].position = position;
+    agents[i].direction = direction;
+}
+
+// Blurs every channel with its 3x3x3 neighborhood by `diffuse_rate` and lowers it by `decay_rate`.
+@compute
+@workgroup_size(4, 4, 4)
+fn main_diffuse(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id >= params.size)) {
+        return;
+    }
+
+    let cell = vec3<i32>(id);
+    var sum = vec4<f32>(0.0);
+
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var z = -1; z <= 1; z++) {
+                sum += textureLoad(volume, wrap(cell + vec3<i32>(x, y, z)));
+            }
+        }
+    }
+
+    let weights = textureLoad(volume, cell);
+    var result = vec4<f32>(0.0);
+
+    for (var k = 0u; k < min(arrayLength(&species), 4u); k++) {
+        let spec = species[k];
+        let diffuse_rate = spec.diffuse_rate * params.time_delta;
+        let diffused = weights[k] * (1.0 - diffuse_rate) + sum[k] / 27.0 * diffuse_rate;
+        result[k] = max(0.0, diffused - spec.decay_rate * params.time_delta);
+    }
+
+    textureStore(volume, cell, result);
+}
@@ -0,0 +1,84 @@
+struct Camera {
+    eye: vec3<f32>,
+    steps: u32,
+    right: vec3<f32>,
+    density: f32,
+    up: vec3<f32>,
+    species_count: u32,
+    forward: vec3<f32>,
+    _p0: u32,
+    // Half the volume's size, with its longest side scaled to 1.
+    extent: vec3<f32>,
+    _p1: u32,
+    colors: array<vec4<f32>, 4>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> camera: Camera;
+
+@group(0)
+@binding(1)
+var volume: texture_3d<f32>;
+
+@group(0)
+@binding(2)
+var s: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// Fullscreen triangle, no vertex buffer needed.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.ndc = uv * 2.0 - 1.0;
+    return out;
+}
+
+// Emission and absorption along the view ray through the volume's bounding box.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(camera.forward + in.ndc.x * camera.right + in.ndc.y * camera.up);
+
+    let t0 = (-camera.extent - camera.eye) / direction;
+    let t1 = (camera.extent - camera.eye) / direction;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+
+    if (far <= max(near, 0.0)) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let start = max(near, 0.0);
+    let step = (far - start) / f32(camera.steps);
+    var color = vec3<f32>(0.0);
+    var transmittance = 1.0;
+
+    for (var i = 0u; i < camera.steps; i++) {
+        let position = camera.eye + direction * (start + (f32(i) + 0.5) * step);
+        let weights = textureSampleLevel(volume, s, position / camera.extent * 0.5 + 0.5, 0.0);
+
+        var total = 0.0;
+        var emitted = vec3<f32>(0.0);
+        for (var k = 0u; k < camera.species_count; k++) {
+            total += weights[k];
+            emitted += camera.colors[k].rgb * weights[k];
+        }
+
+        let alpha = 1.0 - exp(-camera.density * total * step);
+        color += transmittance * alpha * emitted / max(total, 0.0001);
+        transmittance *= 1.0 - alpha;
+
+        if (transmittance < 0.01) {
+            break;
+        }
+    }
+
+    return vec4<f32>(color, 1.0);
+}